@@ -0,0 +1,364 @@
+/// Where a sprite packed by `AtlasBuilder` ended up.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AtlasSprite {
+    /// Index of the texture holding the sprite in `Atlas::pages`.
+    pub page: usize,
+    /// Top-left and bottom-right texture coordinates of the sprite.
+    pub uv: [f32; 4],
+    /// Size of the sprite in pixels.
+    pub size: (u32, u32),
+}
+
+/// Textures packed by `AtlasBuilder`, with the sprites they hold keyed by name.
+pub struct Atlas {
+    pub pages: Vec<super::texture::Texture>,
+    pub sprites: std::collections::HashMap<String, AtlasSprite>,
+}
+
+impl Atlas {
+    pub fn sprite(&self, name: &str) -> Option<&AtlasSprite> {
+        self.sprites.get(name)
+    }
+}
+
+/// Packs images of any size into as few textures as fit them, so sprites that
+/// don't share a spritesheet can share a texture, and so a draw call.
+pub struct AtlasBuilder {
+    /// Largest width and height of a page, lowered to what the device supports.
+    max_size: u32,
+    /// Transparent pixels between sprites.
+    padding: u32,
+    /// Pixels the edges of each sprite are repeated outwards, so filtering and
+    /// rounding near its edges don't sample its neighbours.
+    extrude: u32,
+    images: Vec<(String, image::RgbaImage)>,
+}
+
+impl AtlasBuilder {
+    pub fn new(max_size: u32, padding: u32, extrude: u32) -> Self {
+        Self {
+            max_size,
+            padding,
+            extrude,
+            images: vec![],
+        }
+    }
+
+    /// Adds the sprite `name`, replacing any other with that name.
+    pub fn add(&mut self, name: &str, image: image::RgbaImage) {
+        self.images.retain(|(other, _)| other != name);
+        self.images.push((name.to_string(), image));
+    }
+
+    /// Adds each sprite of `image`, laid out as `sheet`, as `name` followed by its index.
+    pub fn add_sheet(
+        &mut self,
+        name: &str,
+        image: &image::RgbaImage,
+        sheet: &super::pipeline::SpriteSheet,
+    ) {
+        for index in 0..sheet.columns * sheet.rows {
+            let (x, y, width, height) = sheet.rect(index);
+            self.add(
+                &format!("{name}{index}"),
+                image::imageops::crop_imm(image, x, y, width, height).to_image(),
+            );
+        }
+    }
+
+    /// Packs the sprites added and uploads the pages they're packed in.
+    pub fn build(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<Atlas> {
+        let max_size = self.max_size.min(device.limits().max_texture_dimension_2d);
+        let (pages, sprites) = self.pack(max_size)?;
+        let pages = pages
+            .into_iter()
+            .enumerate()
+            .map(|(i, page)| {
+                super::texture::Texture::from_image(
+                    device,
+                    queue,
+                    &image::DynamicImage::ImageRgba8(page),
+                    Some(&format!("atlas_page_{i}")),
+                )
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Atlas { pages, sprites })
+    }
+
+    /// Packs the sprites in shelves, rows as tall as their first sprite, tallest
+    /// sprites first. Pages are cropped to the sprites they hold.
+    fn pack(
+        &self,
+        max_size: u32,
+    ) -> anyhow::Result<(
+        Vec<image::RgbaImage>,
+        std::collections::HashMap<String, AtlasSprite>,
+    )> {
+        let border = 2 * self.extrude + self.padding;
+        // The padding after the last sprites of a row or column is cropped
+        let limit = max_size + self.padding;
+        let mut order = (0..self.images.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| {
+            let (width, height) = self.images[i].1.dimensions();
+            std::cmp::Reverse((height, width))
+        });
+
+        // Rows as wide as a square holding every sprite, pages don't end up as strips
+        let slots = self
+            .images
+            .iter()
+            .map(|(_, image)| (image.width() + border, image.height() + border));
+        let area = slots.clone().map(|(w, h)| w as f64 * h as f64).sum::<f64>();
+        let width = (area.sqrt().ceil() as u32)
+            .next_power_of_two()
+            .max(slots.map(|(width, _)| width).max().unwrap_or(0))
+            .min(limit);
+
+        let mut pages: Vec<Page> = vec![];
+        let mut placements = vec![];
+        for i in order {
+            let (name, image) = &self.images[i];
+            let slot = (image.width() + border, image.height() + border);
+            if slot.0 > limit || slot.1 > limit {
+                anyhow::bail!(
+                    "sprite {name} of {}x{} doesn't fit a {max_size}x{max_size} atlas page",
+                    image.width(),
+                    image.height()
+                );
+            }
+
+            let position = pages.iter_mut().enumerate().find_map(|(page_index, page)| {
+                Some((page_index, page.place(slot, (width, limit))?))
+            });
+            let (page_index, position) = match position {
+                Some(placed) => placed,
+                None => {
+                    let mut page = Page::default();
+                    let position = page
+                        .place(slot, (width, limit))
+                        .expect("a slot fits a new page");
+                    pages.push(page);
+                    (pages.len() - 1, position)
+                }
+            };
+            placements.push((name, image, page_index, position));
+        }
+
+        let mut images = pages
+            .iter()
+            .map(|page| {
+                let (width, height) = page.used_size(self.padding);
+                image::RgbaImage::new(width.max(1), height.max(1))
+            })
+            .collect::<Vec<_>>();
+        let mut sprites = std::collections::HashMap::new();
+        for (name, image, page_index, (x, y)) in placements {
+            let page = &mut images[page_index];
+            let (x, y) = (x + self.extrude, y + self.extrude);
+            self.blit(page, image, x, y);
+
+            let (page_width, page_height) = (page.width() as f32, page.height() as f32);
+            sprites.insert(
+                name.clone(),
+                AtlasSprite {
+                    page: page_index,
+                    uv: [
+                        x as f32 / page_width,
+                        y as f32 / page_height,
+                        (x + image.width()) as f32 / page_width,
+                        (y + image.height()) as f32 / page_height,
+                    ],
+                    size: image.dimensions(),
+                },
+            );
+        }
+        Ok((images, sprites))
+    }
+
+    /// Copies `image` to `page` at `x`, `y`, extruding its edges around it.
+    fn blit(&self, page: &mut image::RgbaImage, image: &image::RgbaImage, x: u32, y: u32) {
+        let extrude = self.extrude as i64;
+        let (width, height) = (image.width() as i64, image.height() as i64);
+        if width == 0 || height == 0 {
+            return;
+        }
+        for dy in -extrude..height + extrude {
+            for dx in -extrude..width + extrude {
+                let pixel = image.get_pixel(
+                    dx.clamp(0, width - 1) as u32,
+                    dy.clamp(0, height - 1) as u32,
+                );
+                page.put_pixel((x as i64 + dx) as u32, (y as i64 + dy) as u32, *pixel);
+            }
+        }
+    }
+}
+
+/// Rows of sprites packed in a page so far.
+#[derive(Default)]
+struct Page {
+    /// Top, height and filled width of each shelf.
+    shelves: Vec<(u32, u32, u32)>,
+}
+
+impl Page {
+    /// Top-left corner of a free `slot` of the page, taken from then on, when
+    /// there's one within `limit`.
+    fn place(&mut self, slot: (u32, u32), limit: (u32, u32)) -> Option<(u32, u32)> {
+        // Sprites come tallest first, so a shelf with room is tall enough
+        if let Some(shelf) = self
+            .shelves
+            .iter_mut()
+            .find(|(_, height, width)| slot.1 <= *height && width + slot.0 <= limit.0)
+        {
+            let x = shelf.2;
+            shelf.2 += slot.0;
+            return Some((x, shelf.0));
+        }
+
+        let top = self
+            .shelves
+            .last()
+            .map_or(0, |(top, height, _)| top + height);
+        if top + slot.1 > limit.1 || slot.0 > limit.0 {
+            return None;
+        }
+        self.shelves.push((top, slot.1, slot.0));
+        Some((0, top))
+    }
+
+    /// Size the sprites placed take, without the padding after the last ones.
+    fn used_size(&self, padding: u32) -> (u32, u32) {
+        let width = self.shelves.iter().map(|shelf| shelf.2).max().unwrap_or(0);
+        let height = self
+            .shelves
+            .last()
+            .map_or(0, |(top, height, _)| top + height);
+        (
+            width.saturating_sub(padding),
+            height.saturating_sub(padding),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    /// A sprite filled with `id` and its pixel coordinates.
+    fn sprite(id: u8, width: u32, height: u32) -> image::RgbaImage {
+        image::RgbaImage::from_fn(width, height, |x, y| {
+            image::Rgba([id, x as u8, y as u8, 255])
+        })
+    }
+
+    /// Pixel rect of `sprite` in its page.
+    fn rect(page: &image::RgbaImage, sprite: &super::AtlasSprite) -> (u32, u32) {
+        (
+            (sprite.uv[0] * page.width() as f32).round() as u32,
+            (sprite.uv[1] * page.height() as f32).round() as u32,
+        )
+    }
+
+    fn builder(max_size: u32, padding: u32, extrude: u32, count: u8) -> super::AtlasBuilder {
+        let mut builder = super::AtlasBuilder::new(max_size, padding, extrude);
+        for id in 0..count {
+            let size = (3 + id as u32 * 7 % 13, 2 + id as u32 * 5 % 11);
+            builder.add(&id.to_string(), sprite(id, size.0, size.1));
+        }
+        builder
+    }
+
+    #[test]
+    fn sprites_keep_their_padding() {
+        let (padding, extrude) = (2, 1);
+        let builder = builder(128, padding, extrude, 40);
+        let (pages, sprites) = builder.pack(128).unwrap();
+
+        // Sprites with their extruded border and padding after them
+        let mut rects: Vec<(usize, u32, u32, u32, u32)> = vec![];
+        for (name, image) in &builder.images {
+            let sprite = sprites[name];
+            let page = &pages[sprite.page];
+            let (x, y) = rect(page, &sprite);
+            assert!(x + image.width() <= page.width() && y + image.height() <= page.height());
+            for (dx, dy, pixel) in image.enumerate_pixels() {
+                assert_eq!(page.get_pixel(x + dx, y + dy), pixel);
+            }
+
+            let rect = (
+                sprite.page,
+                x - extrude,
+                y - extrude,
+                x + image.width() + extrude + padding,
+                y + image.height() + extrude + padding,
+            );
+            for other in &rects {
+                let apart = other.0 != rect.0
+                    || other.3 <= rect.1
+                    || rect.3 <= other.1
+                    || other.4 <= rect.2
+                    || rect.4 <= other.2;
+                assert!(apart, "{:?} overlaps {:?}", rect, other);
+            }
+            rects.push(rect);
+        }
+    }
+
+    #[test]
+    fn edges_are_extruded() {
+        let mut builder = super::AtlasBuilder::new(64, 1, 2);
+        builder.add("a", sprite(1, 5, 4));
+        builder.add("b", sprite(2, 3, 6));
+        let (pages, sprites) = builder.pack(64).unwrap();
+
+        for (name, image) in &builder.images {
+            let sprite = sprites[name];
+            let page = &pages[sprite.page];
+            let (x, y) = rect(page, &sprite);
+            let (right, bottom) = (image.width() - 1, image.height() - 1);
+            for offset in 1..=2 {
+                assert_eq!(page.get_pixel(x - offset, y + 1), image.get_pixel(0, 1));
+                assert_eq!(
+                    page.get_pixel(x + right + offset, y),
+                    image.get_pixel(right, 0)
+                );
+                assert_eq!(page.get_pixel(x + 2, y - offset), image.get_pixel(2, 0));
+                assert_eq!(
+                    page.get_pixel(x + 1, y + bottom + offset),
+                    image.get_pixel(1, bottom)
+                );
+                assert_eq!(
+                    page.get_pixel(x - offset, y - offset),
+                    image.get_pixel(0, 0)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn overflow_spills_onto_other_pages() {
+        let mut builder = super::AtlasBuilder::new(32, 1, 1);
+        for id in 0..6 {
+            builder.add(&id.to_string(), sprite(id, 12, 12));
+        }
+        let (pages, sprites) = builder.pack(32).unwrap();
+
+        // 15×15 slots, four to a page
+        assert!(pages.len() > 1);
+        assert!(pages
+            .iter()
+            .all(|page| page.width() <= 32 && page.height() <= 32));
+        for page in 0..pages.len() {
+            assert!(sprites.values().any(|sprite| sprite.page == page));
+        }
+    }
+
+    #[test]
+    fn oversize_sprites_are_rejected() {
+        let mut builder = super::AtlasBuilder::new(16, 1, 1);
+        builder.add("fits", sprite(0, 14, 14));
+        assert!(builder.pack(16).is_ok());
+
+        builder.add("too big", sprite(1, 15, 14));
+        assert!(builder.pack(16).is_err());
+    }
+}
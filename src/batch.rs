@@ -0,0 +1,278 @@
+/// A region of a texture drawn by `SpriteBatch`, `half_size` from its center to its sides.
+#[derive(Clone)]
+pub struct Sprite {
+    pub material: super::pipeline::Material,
+    /// Top-left and bottom-right texture coordinates of the region.
+    pub uv: [f32; 4],
+    pub half_size: nalgebra_glm::Vec2,
+}
+
+impl Sprite {
+    /// Sprite packed in an atlas, `materials` being the ones of its pages. It's as
+    /// large in world units as it is in pixels on the maps, two units per pixel.
+    pub fn from_atlas(
+        materials: &[super::pipeline::Material],
+        sprite: &super::atlas::AtlasSprite,
+    ) -> Self {
+        Self {
+            material: materials[sprite.page].clone(),
+            uv: sprite.uv,
+            half_size: nalgebra_glm::vec2(sprite.size.0 as f32, sprite.size.1 as f32),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SpriteRaw {
+    pub transform: [[f32; 4]; 4],
+    pub uv: [f32; 4],
+}
+
+/// Sprites drawn for a frame, any number of them, in as many draw calls as
+/// materials they're drawn with. Sprites are drawn grouped by material, in the
+/// order each material was first drawn with, and in the order they were drawn
+/// within a material.
+pub struct SpriteBatch {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    index_count: u32,
+    instance_buffer: wgpu::Buffer,
+    /// Sprites `instance_buffer` has room for, see `grown_capacity`.
+    capacity: usize,
+    camera_bind_group: std::rc::Rc<wgpu::BindGroup>,
+    /// Sprites drawn since the last `prepare`.
+    groups: SpriteGroups<super::pipeline::Material>,
+    /// Instances of `instance_buffer` each material draws, set by `prepare`.
+    draws: Vec<(super::pipeline::Material, std::ops::Range<u32>)>,
+}
+
+const INITIAL_CAPACITY: usize = 64;
+
+/// Sprites drawn since the last `finish`, grouped by the material `M` they're
+/// drawn with. Materials stay while they're drawn every frame, so their order
+/// and the memory of their sprites are kept.
+struct SpriteGroups<M> {
+    groups: Vec<(M, Vec<SpriteRaw>)>,
+}
+
+impl<M: Clone + PartialEq> SpriteGroups<M> {
+    fn new() -> Self {
+        Self { groups: vec![] }
+    }
+
+    fn push(&mut self, material: &M, sprite: SpriteRaw) {
+        match self.groups.iter_mut().find(|(group, _)| group == material) {
+            Some((_, sprites)) => sprites.push(sprite),
+            None => self.groups.push((material.clone(), vec![sprite])),
+        }
+    }
+
+    /// Sprites drawn since the last `finish`.
+    fn len(&self) -> usize {
+        self.groups.iter().map(|(_, sprites)| sprites.len()).sum()
+    }
+
+    /// Lays the sprites drawn out one group after the other, passing `write` the
+    /// sprites of each group and the instance they start at, and returns the
+    /// instances each material draws. Materials nothing was drawn with are dropped.
+    fn finish(
+        &mut self,
+        mut write: impl FnMut(usize, &[SpriteRaw]),
+    ) -> Vec<(M, std::ops::Range<u32>)> {
+        self.groups.retain(|(_, sprites)| !sprites.is_empty());
+        let mut start = 0;
+        self.groups
+            .iter_mut()
+            .map(|(material, sprites)| {
+                write(start, sprites);
+                let end = start + sprites.len();
+                let draw = (material.clone(), start as u32..end as u32);
+                start = end;
+                sprites.clear();
+                draw
+            })
+            .collect()
+    }
+}
+
+/// Sprites an instance buffer of `capacity` has room for once `count` are drawn,
+/// the next power of two when they don't fit.
+fn grown_capacity(capacity: usize, count: usize) -> usize {
+    match count > capacity {
+        true => count.next_power_of_two(),
+        false => capacity,
+    }
+}
+
+impl SpriteBatch {
+    pub fn new(device: &wgpu::Device, camera_bind_group: std::rc::Rc<wgpu::BindGroup>) -> Self {
+        let (vertex_points, vertex_indices) =
+            super::vertex::get_rect(nalgebra_glm::vec3(1.0, 1.0, 0.0));
+        let vertex_buffer = wgpu::util::DeviceExt::create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Sprite Vertex Buffer"),
+                contents: bytemuck::cast_slice(&vertex_points),
+                usage: wgpu::BufferUsages::VERTEX,
+            },
+        );
+        let index_buffer = wgpu::util::DeviceExt::create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Sprite Index Buffer"),
+                contents: bytemuck::cast_slice(&vertex_indices),
+                usage: wgpu::BufferUsages::INDEX,
+            },
+        );
+
+        Self {
+            vertex_buffer,
+            index_buffer,
+            index_count: vertex_indices.len() as u32,
+            instance_buffer: Self::create_instance_buffer(device, INITIAL_CAPACITY),
+            capacity: INITIAL_CAPACITY,
+            camera_bind_group,
+            groups: SpriteGroups::new(),
+            draws: vec![],
+        }
+    }
+
+    fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sprite Instance Buffer"),
+            size: (capacity * std::mem::size_of::<SpriteRaw>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Draws `sprite` at `transform` this frame, mirrored horizontally while its
+    /// `flip_x` is 0 and vertically when its `flip_y` is 1.
+    pub fn draw(&mut self, sprite: &Sprite, transform: &super::transform::Transform) {
+        let [mut left, mut top, mut right, mut bottom] = sprite.uv;
+        if transform.flip_x == 0 {
+            std::mem::swap(&mut left, &mut right);
+        }
+        if transform.flip_y == 1 {
+            std::mem::swap(&mut top, &mut bottom);
+        }
+        let raw = SpriteRaw {
+            transform: (transform.matrix
+                * nalgebra_glm::scaling(&nalgebra_glm::vec3(
+                    sprite.half_size.x,
+                    sprite.half_size.y,
+                    1.0,
+                )))
+            .into(),
+            uv: [left, top, right, bottom],
+        };
+        self.groups.push(&sprite.material, raw);
+    }
+
+    /// Uploads the sprites drawn since the last call, growing the instance buffer
+    /// when they don't fit, to be rendered until the next call.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let capacity = grown_capacity(self.capacity, self.groups.len());
+        if capacity != self.capacity {
+            self.capacity = capacity;
+            self.instance_buffer = Self::create_instance_buffer(device, self.capacity);
+        }
+
+        let instance_buffer = &self.instance_buffer;
+        self.draws = self.groups.finish(|start, sprites| {
+            queue.write_buffer(
+                instance_buffer,
+                (start * std::mem::size_of::<SpriteRaw>()) as wgpu::BufferAddress,
+                bytemuck::cast_slice(sprites),
+            )
+        });
+    }
+
+    /// Renders the sprites of the last `prepare`, a draw call per material.
+    pub fn render<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if self.draws.is_empty() {
+            return;
+        }
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+        for (material, instances) in &self.draws {
+            render_pass.set_pipeline(&material.pipeline);
+            render_pass.set_bind_group(0, &material.bind_group, &[]);
+            render_pass.draw_indexed(0..self.index_count, 0, instances.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    /// A sprite told apart by its `uv`.
+    fn sprite(id: u32) -> super::SpriteRaw {
+        super::SpriteRaw {
+            transform: nalgebra_glm::Mat4::identity().into(),
+            uv: [id as f32; 4],
+        }
+    }
+
+    /// Draws `sprites`, pairs of a material and a sprite, as a frame and returns
+    /// its draws along with the sprites of the instance buffer.
+    fn frame(
+        groups: &mut super::SpriteGroups<char>,
+        sprites: &[(char, u32)],
+    ) -> (Vec<(char, std::ops::Range<u32>)>, Vec<u32>) {
+        for &(material, id) in sprites {
+            groups.push(&material, sprite(id));
+        }
+        let mut instances = vec![0; groups.len()];
+        let draws = groups.finish(|start, sprites| {
+            for (i, sprite) in sprites.iter().enumerate() {
+                instances[start + i] = sprite.uv[0] as u32;
+            }
+        });
+        (draws, instances)
+    }
+
+    #[test]
+    fn draws_each_material_once() {
+        let mut groups = super::SpriteGroups::new();
+        let (draws, instances) = frame(&mut groups, &[('a', 1), ('b', 2), ('a', 3), ('c', 4)]);
+        assert_eq!(draws, [('a', 0..2), ('b', 2..3), ('c', 3..4)]);
+        assert_eq!(instances, [1, 3, 2, 4]);
+    }
+
+    #[test]
+    fn keeps_the_order_of_materials_between_frames() {
+        let mut groups = super::SpriteGroups::new();
+        frame(&mut groups, &[('a', 1), ('b', 2)]);
+        let (draws, instances) = frame(&mut groups, &[('b', 3), ('a', 4), ('a', 5)]);
+        assert_eq!(draws, [('a', 0..2), ('b', 2..3)]);
+        assert_eq!(instances, [4, 5, 3]);
+    }
+
+    #[test]
+    fn drops_materials_of_despawned_sprites() {
+        let mut groups = super::SpriteGroups::new();
+        frame(&mut groups, &[('a', 1), ('b', 2)]);
+        let (draws, _) = frame(&mut groups, &[('b', 3), ('c', 4)]);
+        assert_eq!(draws, [('b', 0..1), ('c', 1..2)]);
+
+        // Spawned again, a material goes after the ones still drawn
+        let (draws, _) = frame(&mut groups, &[('a', 5), ('c', 6)]);
+        assert_eq!(draws, [('c', 0..1), ('a', 1..2)]);
+
+        let (draws, instances) = frame(&mut groups, &[]);
+        assert!(draws.is_empty());
+        assert!(instances.is_empty());
+    }
+
+    #[test]
+    fn grows_to_the_next_power_of_two() {
+        assert_eq!(super::grown_capacity(64, 0), 64);
+        assert_eq!(super::grown_capacity(64, 64), 64);
+        assert_eq!(super::grown_capacity(64, 65), 128);
+        assert_eq!(super::grown_capacity(64, 300), 512);
+        assert_eq!(super::grown_capacity(512, 100), 512);
+    }
+}
@@ -0,0 +1,296 @@
+use parry2d::bounding_volume::BoundingVolume;
+use wgpu::util::DeviceExt;
+
+/// Size in tiles of the chunks finite layers are split into.
+pub const CHUNK_SIZE: u32 = 16;
+
+/// Distance in world units around the view where chunks get loaded,
+/// they are unloaded once they are twice as far.
+const LOAD_MARGIN: f32 = 256.0;
+
+/// Key the cells of a chunk are drawn at, see `draw_groups`.
+type DrawKey = (i32, (i32, i32));
+
+/// Keeps GPU buffers only for the chunks of a tile layer around the camera.
+pub struct ChunkStreamer {
+    pub layer: usize,
+    bounds: Vec<((i32, i32), (u32, u32))>,
+    layer_buffer: wgpu::Buffer,
+    layer_bind_group: std::rc::Rc<wgpu::BindGroup>,
+    /// Keys of `loaded` of each loaded chunk, by position.
+    chunks: std::collections::BTreeMap<(i32, i32), Vec<DrawKey>>,
+    /// Renders of the loaded chunks, sorted so tiles draw in the order Tiled does.
+    loaded: std::collections::BTreeMap<DrawKey, Vec<super::map::TileRender>>,
+}
+
+/// Row of `Map::draw_key` the cell (x, y) is drawn in. Tiles of non-orthogonal
+/// maps overlap the ones of the next rows, across chunks too, so their chunks
+/// are drawn a row at a time. Orthogonal chunks are drawn whole, as row 0.
+fn draw_row(map: &super::map::Map, x: i32, y: i32) -> i32 {
+    match map.orientation {
+        super::map::Orientation::Orthogonal => 0,
+        _ => map.draw_key(x, y).0,
+    }
+}
+
+/// Cells of the chunk at `position` grouped by row, each keyed by its row and
+/// its first cell in draw order.
+fn draw_groups(
+    map: &super::map::Map,
+    position: (i32, i32),
+    size: (u32, u32),
+) -> Vec<(DrawKey, Vec<(i32, i32)>)> {
+    let mut rows = std::collections::BTreeMap::<_, Vec<_>>::new();
+    for y in position.1..position.1 + size.1 as i32 {
+        for x in position.0..position.0 + size.0 as i32 {
+            rows.entry(draw_row(map, x, y)).or_default().push((x, y));
+        }
+    }
+    rows.into_iter()
+        .map(|(row, cells)| {
+            let first = cells.iter().map(|&(x, y)| map.draw_key(x, y)).min();
+            ((row, first.unwrap()), cells)
+        })
+        .collect()
+}
+
+impl ChunkStreamer {
+    pub fn new(
+        layer: usize,
+        map: &super::map::Map,
+        tile_renderer: &super::map::TileRenderer,
+        device: &wgpu::Device,
+    ) -> Self {
+        let layer_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Layer Buffer"),
+            contents: bytemuck::cast_slice(&[super::map::LayerUniform::new(
+                map,
+                layer,
+                nalgebra_glm::vec2(0.0, 0.0),
+            )]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        Self {
+            layer,
+            bounds: map.chunk_bounds(layer, CHUNK_SIZE),
+            layer_bind_group: tile_renderer.create_layer_bind_group(device, &layer_buffer),
+            layer_buffer,
+            chunks: std::collections::BTreeMap::new(),
+            loaded: std::collections::BTreeMap::new(),
+        }
+    }
+
+    /// Generates the renders of the chunk at `position`, replacing the ones it had.
+    fn load(
+        &mut self,
+        map: &super::map::Map,
+        tile_renderer: &super::map::TileRenderer,
+        device: &wgpu::Device,
+        position: (i32, i32),
+        size: (u32, u32),
+    ) {
+        let mut keys = Vec::new();
+        for (key, cells) in draw_groups(map, position, size) {
+            let renders = tile_renderer.generate_render(
+                self.layer,
+                map,
+                device,
+                cells,
+                &self.layer_bind_group,
+            );
+            self.loaded.insert(key, renders);
+            keys.push(key);
+        }
+        self.chunks.insert(position, keys);
+    }
+
+    fn chunk_aabb(
+        map: &super::map::Map,
+        position: (i32, i32),
+        size: (u32, u32),
+    ) -> parry2d::bounding_volume::Aabb {
+        let (right, bottom) = (
+            position.0 + size.0 as i32 - 1,
+            position.1 + size.1 as i32 - 1,
+        );
+        let corners = [
+            map.cell_to_world(position.0, position.1),
+            map.cell_to_world(right, position.1),
+            map.cell_to_world(position.0, bottom),
+            map.cell_to_world(right, bottom),
+        ]
+        .map(|corner| nalgebra::Point2::new(corner.x, corner.y));
+
+        // Margin for the cells around the corner centers and tiles bigger than the grid
+        let margin = map
+            .tilesets
+            .iter()
+            .map(|tileset| tileset.tile_size.0.max(tileset.tile_size.1))
+            .fold(map.tile_size.0.max(map.tile_size.1), u32::max) as f32
+            * 2.0;
+
+        parry2d::bounding_volume::Aabb::from_points(&corners).loosened(margin)
+    }
+
+    /// Moves the layer with its offset and parallax, loads the chunks close
+    /// to `view` (in world units) and unloads the far away ones.
+    pub fn update(
+        &mut self,
+        view: &parry2d::bounding_volume::Aabb,
+        map: &super::map::Map,
+        tile_renderer: &super::map::TileRenderer,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) {
+        let camera = nalgebra_glm::vec2(view.center().x, view.center().y);
+        queue.write_buffer(
+            &self.layer_buffer,
+            0,
+            bytemuck::cast_slice(&[super::map::LayerUniform::new(map, self.layer, camera)]),
+        );
+
+        let shift = map.layer_shift(self.layer, camera);
+        let shift = nalgebra::Isometry2::translation(shift.x, shift.y);
+        let load_view = view.loosened(LOAD_MARGIN);
+        let keep_view = view.loosened(LOAD_MARGIN * 2.0);
+
+        for index in 0..self.bounds.len() {
+            let (position, size) = self.bounds[index];
+            let aabb = Self::chunk_aabb(map, position, size).transform_by(&shift);
+            let loaded = self.chunks.contains_key(&position);
+
+            if !loaded && aabb.intersects(&load_view) {
+                self.load(map, tile_renderer, device, position, size);
+            } else if loaded && !aabb.intersects(&keep_view) {
+                for key in self.chunks.remove(&position).unwrap_or_default() {
+                    self.loaded.remove(&key);
+                }
+            }
+        }
+    }
+
+    /// Updates the render of the cells `cells` after they were set with `Map::set_tile`.
+    /// Only their instances are rewritten when they have one, otherwise their chunks
+    /// are generated again, once each. Chunks that aren't loaded get the tiles once they are.
+    pub fn set_tiles(
+        &mut self,
+        cells: &[(i32, i32)],
+        map: &super::map::Map,
+        tile_renderer: &super::map::TileRenderer,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) {
+        let mut regenerate = Vec::new();
+        let mut new_chunks = false;
+        for &(x, y) in cells {
+            let contains = |&&(position, size): &&((i32, i32), (u32, u32))| {
+                (position.0..position.0 + size.0 as i32).contains(&x)
+                    && (position.1..position.1 + size.1 as i32).contains(&y)
+            };
+            let Some(&(position, size)) = self.bounds.iter().find(contains) else {
+                new_chunks = true;
+                continue;
+            };
+
+            let row = draw_row(map, x, y);
+            let Some(renders) = self.chunks.get(&position).and_then(|keys| {
+                let key = keys.iter().find(|key| key.0 == row)?;
+                self.loaded.get_mut(key)
+            }) else {
+                continue;
+            };
+            if !super::map::TileRender::set_tile(renders, self.layer, map, x, y, queue)
+                && !regenerate.contains(&(position, size))
+            {
+                regenerate.push((position, size));
+            }
+        }
+
+        for (position, size) in regenerate {
+            self.load(map, tile_renderer, device, position, size);
+        }
+        if new_chunks {
+            // Chunks were added to the infinite map, they're loaded on the next update
+            self.bounds = map.chunk_bounds(self.layer, CHUNK_SIZE);
+        }
+    }
+
+    /// Advances the animated tiles of the loaded chunks to `time`.
+    pub fn animate(
+        &mut self,
+        map: &super::map::Map,
+        time: std::time::Duration,
+        queue: &wgpu::Queue,
+    ) {
+        self.loaded
+            .values_mut()
+            .flatten()
+            .for_each(|tile_render| tile_render.animate(map, time, queue));
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        self.loaded
+            .values()
+            .flatten()
+            .for_each(|tile_render| tile_render.render.draw(render_pass));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::map::{Map, Orientation, RenderOrder};
+
+    fn load(orientation: Orientation) -> Map {
+        let mut map =
+            pollster::block_on(super::super::map::load_map("./resources/mapa.json")).unwrap();
+        map.orientation = orientation;
+        map
+    }
+
+    /// Cells of the first layer in the order `ChunkStreamer` draws them once loaded.
+    fn drawn_cells(map: &Map) -> Vec<(i32, i32)> {
+        let mut groups = std::collections::BTreeMap::new();
+        for (position, size) in map.chunk_bounds(0, super::CHUNK_SIZE) {
+            groups.extend(super::draw_groups(map, position, size));
+        }
+        assert_eq!(
+            groups.values().map(Vec::len).sum::<usize>(),
+            (map.size.0 * map.size.1) as usize
+        );
+        // Sorted within each render like `TileRenderer::generate_render` does
+        groups
+            .into_values()
+            .flat_map(|mut cells| {
+                cells.sort_by_key(|&(x, y)| map.draw_key(x, y));
+                cells
+            })
+            .collect()
+    }
+
+    #[test]
+    fn isometric_tiles_draw_in_order_across_chunk_seams() {
+        let map = load(Orientation::Isometric);
+        assert!(map.chunk_bounds(0, super::CHUNK_SIZE).len() > 1);
+
+        let cells = drawn_cells(&map);
+        let keys = cells
+            .iter()
+            .map(|&(x, y)| map.draw_key(x, y))
+            .collect::<Vec<_>>();
+        assert!(keys.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        // The first cell of the next chunk is behind most of the first chunk
+        let index = |cell| cells.iter().position(|&other| other == cell).unwrap();
+        assert!(index((16, 0)) < index((15, 2)));
+    }
+
+    #[test]
+    fn orthogonal_chunks_draw_whole() {
+        let mut map = load(Orientation::Orthogonal);
+        map.render_order = RenderOrder::RightDown;
+        let cells = drawn_cells(&map);
+        assert_eq!(cells[..16], (0..16).map(|x| (x, 0)).collect::<Vec<_>>());
+        assert_eq!(cells[16], (0, 1));
+    }
+}
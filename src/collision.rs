@@ -0,0 +1,160 @@
+use parry2d::bounding_volume::BoundingVolume;
+
+/// Segments of the polygons ellipses are approximated with.
+const ELLIPSE_SEGMENTS: usize = 16;
+
+/// A solid part of the map, in world units.
+pub struct Collider {
+    position: nalgebra::Isometry2<f32>,
+    shape: parry2d::shape::SharedShape,
+    aabb: parry2d::bounding_volume::Aabb,
+}
+
+impl Collider {
+    fn new(position: nalgebra::Isometry2<f32>, shape: parry2d::shape::SharedShape) -> Self {
+        Self {
+            aabb: shape.compute_aabb(&position),
+            position,
+            shape,
+        }
+    }
+
+    /// Collider of the tile at cell (x, y) of a layer. The shapes drawn in the
+    /// tileset collision editor are used when there are any, otherwise the
+    /// whole cell (a rectangle, diamond or hexagon) blocks. Tiles whose shapes
+    /// are only points or polylines don't collide.
+    pub fn from_tile(
+        map: &super::map::Map,
+        tile: super::map::Tile,
+        x: i32,
+        y: i32,
+    ) -> Option<Self> {
+        let cell = map.cell_to_world(x, y);
+        let (tileset_id, local_id) = map.resolve_gid(tile.gid)?;
+        let tileset = &map.tilesets[tileset_id];
+
+        let objects = match tileset.tiles.get(&local_id) {
+            Some(data) if !data.objects.is_empty() => &data.objects,
+            _ => {
+                let outline = map
+                    .cell_outline()
+                    .iter()
+                    .map(|point| nalgebra::Point2::new(point.x, point.y))
+                    .collect::<Vec<_>>();
+                return Some(Self::new(
+                    nalgebra::Isometry2::translation(cell.x, cell.y),
+                    parry2d::shape::SharedShape::convex_hull(&outline)?,
+                ));
+            }
+        };
+
+        // Same anchor as the tile quad, see `TileRenderer::generate_render`
+        let tile_size = (tileset.tile_size.0 as f32, tileset.tile_size.1 as f32);
+        let center = cell + map.tile_anchor(tileset.tile_size);
+
+        // Tile pixels (y down) to world units relative to the tile center,
+        // flipped like the tile is
+        let to_local = |(mut x, mut y): (f32, f32)| {
+            if tile.flip_diagonal {
+                (x, y) = (y, x);
+            }
+            if tile.flip_horizontal {
+                x = tile_size.0 - x;
+            }
+            if tile.flip_vertical {
+                y = tile_size.1 - y;
+            }
+            nalgebra::Point2::new(x * 2.0 - tile_size.0, tile_size.1 - y * 2.0)
+        };
+
+        let parts = objects
+            .iter()
+            .flat_map(|object| {
+                let points = object_points(object)
+                    .into_iter()
+                    .map(to_local)
+                    .collect::<Vec<_>>();
+                polygon_parts(&points)
+            })
+            .collect::<Vec<_>>();
+        if parts.is_empty() {
+            return None;
+        }
+
+        Some(Self::new(
+            nalgebra::Isometry2::translation(center.x, center.y),
+            parry2d::shape::SharedShape::compound(parts),
+        ))
+    }
+
+    pub fn intersects(
+        &self,
+        shape: &dyn parry2d::shape::Shape,
+        position: &nalgebra::Isometry2<f32>,
+    ) -> bool {
+        self.aabb.intersects(&shape.compute_aabb(position))
+            && parry2d::query::intersection_test(&self.position, &*self.shape, position, shape)
+                .unwrap()
+    }
+}
+
+/// Outline of a solid object in Tiled pixels relative to its tile, rotated.
+fn object_points(object: &super::map::Object) -> Vec<(f32, f32)> {
+    let (width, height) = object.size;
+    let points = match &object.shape {
+        super::map::Shape::Rectangle => {
+            vec![(0.0, 0.0), (width, 0.0), (width, height), (0.0, height)]
+        }
+        super::map::Shape::Ellipse => (0..ELLIPSE_SEGMENTS)
+            .map(|i| {
+                let angle = i as f32 / ELLIPSE_SEGMENTS as f32 * std::f32::consts::TAU;
+                (
+                    width * 0.5 * (1.0 + angle.cos()),
+                    height * 0.5 * (1.0 + angle.sin()),
+                )
+            })
+            .collect(),
+        super::map::Shape::Polygon(points) => points.clone(),
+        super::map::Shape::Point | super::map::Shape::Polyline(_) => vec![],
+    };
+
+    // Tiled rotates clockwise around the object position
+    let (sin, cos) = object.rotation.to_radians().sin_cos();
+    points
+        .into_iter()
+        .map(|(x, y)| {
+            (
+                object.position.0 + x * cos - y * sin,
+                object.position.1 + x * sin + y * cos,
+            )
+        })
+        .collect()
+}
+
+/// Convex polygons become a single convex part, concave ones are decomposed in several.
+fn polygon_parts(
+    points: &[nalgebra::Point2<f32>],
+) -> Vec<(nalgebra::Isometry2<f32>, parry2d::shape::SharedShape)> {
+    if points.len() < 3 {
+        return vec![];
+    }
+    let Some(convex_hull) = parry2d::shape::SharedShape::convex_hull(points) else {
+        return vec![];
+    };
+
+    let is_convex = convex_hull
+        .as_convex_polygon()
+        .is_some_and(|polygon| polygon.points().len() == points.len());
+    if is_convex {
+        return vec![(nalgebra::Isometry2::identity(), convex_hull)];
+    }
+
+    // Compounds can't be nested, so the decomposition is flattened into the tile compound
+    let indices = (0..points.len() as u32)
+        .map(|i| [i, (i + 1) % points.len() as u32])
+        .collect::<Vec<_>>();
+    parry2d::shape::SharedShape::convex_decomposition(points, &indices)
+        .as_compound()
+        .map(|compound| compound.shapes().to_vec())
+        .unwrap_or_default()
+}
@@ -0,0 +1,606 @@
+use wgpu::util::DeviceExt;
+
+/// Tiles per row of the palette.
+const PALETTE_COLUMNS: u32 = 16;
+
+/// Rows of the palette, the rest of the tileset is scrolled into view.
+const PALETTE_ROWS: u32 = 4;
+
+/// What the mouse does on the map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tool {
+    Paint,
+    Erase,
+    /// Fills the box between the cells the mouse is pressed and released on.
+    Box,
+    /// Fills the cells connected to the clicked one that have its tile.
+    Fill,
+}
+
+/// A cell (x, y) of the tile layer `layer` changed from `old` to `new`.
+#[derive(Debug, Clone, Copy)]
+pub struct Edit {
+    pub layer: usize,
+    pub x: i32,
+    pub y: i32,
+    pub old: super::map::Tile,
+    pub new: super::map::Tile,
+}
+
+/// In-game map editor. Paints the tile picked from a palette of a tileset on a
+/// tile layer, keeps the edits for undo and redo and saves the map as Tiled JSON.
+/// The edits it returns are applied by the caller, who passes them to `record`.
+pub struct Editor {
+    pub enabled: bool,
+    pub tool: Tool,
+    /// Tile layer being edited.
+    pub layer: usize,
+    tileset: usize,
+    /// Local id of the tile painted.
+    tile: u32,
+    /// Local id of the first tile of the palette.
+    first_tile: u32,
+    /// Where the map is saved, see `save_path`.
+    path: String,
+    /// Cell the mouse was pressed on, while it's held.
+    pressed: Option<(i32, i32)>,
+    /// Edits since the mouse was pressed, they are undone together.
+    stroke: Vec<Edit>,
+    undo: Vec<Vec<Edit>>,
+    redo: Vec<Vec<Edit>>,
+    /// Set by `new`, the editor works the same without it but shows no palette.
+    palette: Option<Palette>,
+    /// World position of the top left corner of the palette.
+    palette_origin: nalgebra_glm::Vec2,
+}
+
+/// Render of the palette, drawn over the map at `Editor::palette_origin`.
+struct Palette {
+    render: Option<super::map::TileRender>,
+    buffer: wgpu::Buffer,
+    bind_group: std::rc::Rc<wgpu::BindGroup>,
+}
+
+/// File a map loaded from `path` is saved to. Maps are saved as Tiled JSON, so
+/// TMX and LDtk maps are saved to a JSON file next to them instead of over them.
+fn save_path(path: &str) -> String {
+    let path = std::path::Path::new(path);
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("tmx" | "ldtk") => path.with_extension("json").to_string_lossy().into_owned(),
+        _ => path.to_string_lossy().into_owned(),
+    }
+}
+
+impl Editor {
+    pub fn new(
+        map: &super::map::Map,
+        path: &str,
+        tile_renderer: &super::map::TileRenderer,
+        device: &wgpu::Device,
+    ) -> Self {
+        let palette_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Palette Buffer"),
+            contents: bytemuck::cast_slice(&[super::map::LayerUniform::overlay(
+                nalgebra_glm::vec2(0.0, 0.0),
+            )]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let mut editor = Self {
+            palette: Some(Palette {
+                render: None,
+                bind_group: tile_renderer.create_layer_bind_group(device, &palette_buffer),
+                buffer: palette_buffer,
+            }),
+            ..Self::without_palette(map, path)
+        };
+        editor.generate_palette(map, tile_renderer, device);
+        editor
+    }
+
+    /// Editor of `map`, loaded from `path`, without the render of the palette.
+    fn without_palette(map: &super::map::Map, path: &str) -> Self {
+        Self {
+            enabled: false,
+            tool: Tool::Paint,
+            layer: map.tile_layers().next().unwrap_or(0),
+            tileset: 0,
+            tile: 0,
+            first_tile: 0,
+            path: save_path(path),
+            pressed: None,
+            stroke: Vec::new(),
+            undo: Vec::new(),
+            redo: Vec::new(),
+            palette: None,
+            palette_origin: nalgebra_glm::vec2(0.0, 0.0),
+        }
+    }
+
+    /// World size of a palette cell, the size of the tiles of the tileset.
+    fn palette_cell(&self, map: &super::map::Map) -> nalgebra_glm::Vec2 {
+        let tile_size = map
+            .tilesets
+            .get(self.tileset)
+            .map_or((0, 0), |tileset| tileset.tile_size);
+        nalgebra_glm::vec2(tile_size.0 as f32 * 2.0, tile_size.1 as f32 * 2.0)
+    }
+
+    fn generate_palette(
+        &mut self,
+        map: &super::map::Map,
+        tile_renderer: &super::map::TileRenderer,
+        device: &wgpu::Device,
+    ) {
+        let cell = self.palette_cell(map);
+        let Some(palette) = self.palette.as_mut() else {
+            return;
+        };
+        let Some(tileset) = map.tilesets.get(self.tileset) else {
+            palette.render = None;
+            return;
+        };
+
+        let last_tile = (self.first_tile + PALETTE_COLUMNS * PALETTE_ROWS).min(tileset.tile_count);
+        let tiles = (self.first_tile..last_tile)
+            .map(|id| {
+                let index = id - self.first_tile;
+                let (column, row) = (index % PALETTE_COLUMNS, index / PALETTE_COLUMNS);
+                (
+                    id,
+                    nalgebra_glm::vec2(
+                        (column as f32 + 0.5) * cell.x,
+                        -(row as f32 + 0.5) * cell.y,
+                    ),
+                )
+            })
+            .collect::<Vec<_>>();
+        palette.render = Some(tile_renderer.generate_palette(
+            map,
+            self.tileset,
+            &tiles,
+            device,
+            &palette.bind_group,
+        ));
+    }
+
+    /// Keeps the palette on the top left corner of `view` (in world units).
+    pub fn update(&mut self, view: &parry2d::bounding_volume::Aabb, queue: &wgpu::Queue) {
+        self.palette_origin = nalgebra_glm::vec2(view.mins.x, view.maxs.y);
+        if let Some(palette) = &self.palette {
+            queue.write_buffer(
+                &palette.buffer,
+                0,
+                bytemuck::cast_slice(&[super::map::LayerUniform::overlay(self.palette_origin)]),
+            );
+        }
+    }
+
+    /// Local id of the palette tile at the world position `position`.
+    pub fn palette_tile(&self, map: &super::map::Map, position: nalgebra_glm::Vec2) -> Option<u32> {
+        let tileset = map.tilesets.get(self.tileset)?;
+        let cell = self.palette_cell(map);
+        let column = ((position.x - self.palette_origin.x) / cell.x).floor();
+        let row = ((self.palette_origin.y - position.y) / cell.y).floor();
+        if !(0.0..PALETTE_COLUMNS as f32).contains(&column)
+            || !(0.0..PALETTE_ROWS as f32).contains(&row)
+        {
+            return None;
+        }
+
+        let id = self.first_tile + row as u32 * PALETTE_COLUMNS + column as u32;
+        (id < tileset.tile_count).then_some(id)
+    }
+
+    pub fn select(&mut self, tile: u32) {
+        self.tile = tile;
+    }
+
+    /// Selects the tile of the cell (x, y) of the edited layer, and its tileset.
+    pub fn pick(
+        &mut self,
+        map: &super::map::Map,
+        x: i32,
+        y: i32,
+        tile_renderer: &super::map::TileRenderer,
+        device: &wgpu::Device,
+    ) {
+        let Some((tileset, local_id)) = map.resolve_gid(map.tile(self.layer, x, y).gid) else {
+            return;
+        };
+        self.tile = local_id;
+        if tileset != self.tileset {
+            self.tileset = tileset;
+            self.first_tile = 0;
+            self.generate_palette(map, tile_renderer, device);
+        }
+    }
+
+    /// Edits the next tile layer, after the last one the first.
+    pub fn next_layer(&mut self, map: &super::map::Map) {
+        let layers = map.tile_layers().collect::<Vec<_>>();
+        if let Some(position) = layers.iter().position(|&layer| layer == self.layer) {
+            self.layer = layers[(position + 1) % layers.len()];
+        }
+    }
+
+    /// Shows the tileset `step` tilesets away in the palette.
+    pub fn next_tileset(
+        &mut self,
+        map: &super::map::Map,
+        step: isize,
+        tile_renderer: &super::map::TileRenderer,
+        device: &wgpu::Device,
+    ) {
+        if map.tilesets.is_empty() {
+            return;
+        }
+        self.tileset = (self.tileset as isize + step).rem_euclid(map.tilesets.len() as isize) as _;
+        self.tile = 0;
+        self.first_tile = 0;
+        self.generate_palette(map, tile_renderer, device);
+    }
+
+    /// Scrolls the palette `rows` rows down, or up when negative.
+    pub fn scroll(
+        &mut self,
+        map: &super::map::Map,
+        rows: i32,
+        tile_renderer: &super::map::TileRenderer,
+        device: &wgpu::Device,
+    ) {
+        let Some(tileset) = map.tilesets.get(self.tileset) else {
+            return;
+        };
+        let last_row = tileset.tile_count.saturating_sub(1) / PALETTE_COLUMNS;
+        let row = (self.first_tile / PALETTE_COLUMNS) as i32 + rows;
+        self.first_tile = row.clamp(0, last_row as i32) as u32 * PALETTE_COLUMNS;
+        self.generate_palette(map, tile_renderer, device);
+    }
+
+    /// Tile the tool sets the cells to.
+    fn brush(&self, map: &super::map::Map) -> super::map::Tile {
+        match (self.tool, map.tilesets.get(self.tileset)) {
+            (Tool::Erase, _) | (_, None) => super::map::Tile::default(),
+            (_, Some(tileset)) => super::map::Tile {
+                gid: tileset.first_gid + self.tile,
+                ..Default::default()
+            },
+        }
+    }
+
+    fn edit(&self, map: &super::map::Map, x: i32, y: i32, new: super::map::Tile) -> Option<Edit> {
+        let old = map.tile(self.layer, x, y);
+        (old != new).then_some(Edit {
+            layer: self.layer,
+            x,
+            y,
+            old,
+            new,
+        })
+    }
+
+    /// Edits made by pressing the mouse on the cell (x, y).
+    pub fn press(&mut self, map: &super::map::Map, x: i32, y: i32) -> Vec<Edit> {
+        self.pressed = Some((x, y));
+        match self.tool {
+            Tool::Paint | Tool::Erase => self.drag(map, x, y),
+            Tool::Box => Vec::new(),
+            Tool::Fill => self.flood_fill(map, x, y),
+        }
+    }
+
+    /// Edits made by holding the mouse over the world position `position`, none
+    /// while it's over the palette.
+    pub fn hover(&mut self, map: &super::map::Map, position: nalgebra_glm::Vec2) -> Vec<Edit> {
+        if self.palette_tile(map, position).is_some() {
+            return Vec::new();
+        }
+        let (x, y) = map.world_to_cell(position);
+        self.drag(map, x, y)
+    }
+
+    /// Edits made by holding the mouse over the cell (x, y).
+    fn drag(&mut self, map: &super::map::Map, x: i32, y: i32) -> Vec<Edit> {
+        match (self.tool, self.pressed) {
+            (Tool::Paint | Tool::Erase, Some(_)) => {
+                self.edit(map, x, y, self.brush(map)).into_iter().collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Edits made by releasing the mouse on the cell (x, y), which end the stroke.
+    pub fn release(&mut self, map: &super::map::Map, x: i32, y: i32) -> Vec<Edit> {
+        let Some(pressed) = self.pressed.take() else {
+            return Vec::new();
+        };
+        if self.tool != Tool::Box {
+            return Vec::new();
+        }
+
+        let brush = self.brush(map);
+        (pressed.1.min(y)..=pressed.1.max(y))
+            .flat_map(|y| (pressed.0.min(x)..=pressed.0.max(x)).map(move |x| (x, y)))
+            .filter_map(|(x, y)| self.edit(map, x, y, brush))
+            .collect()
+    }
+
+    /// Edits setting the brush on the cells connected to (x, y) that have its tile,
+    /// within the chunks of the layer.
+    fn flood_fill(&self, map: &super::map::Map, x: i32, y: i32) -> Vec<Edit> {
+        let (min, max) = map
+            .chunk_bounds(self.layer, super::chunk::CHUNK_SIZE)
+            .iter()
+            .fold(
+                ((i32::MAX, i32::MAX), (i32::MIN, i32::MIN)),
+                |(min, max), &(position, size)| {
+                    (
+                        (min.0.min(position.0), min.1.min(position.1)),
+                        (
+                            max.0.max(position.0 + size.0 as i32),
+                            max.1.max(position.1 + size.1 as i32),
+                        ),
+                    )
+                },
+            );
+        let target = map.tile(self.layer, x, y);
+        let brush = self.brush(map);
+        if target == brush {
+            return Vec::new();
+        }
+
+        let mut edits = Vec::new();
+        let mut visited = std::collections::HashSet::new();
+        let mut cells = vec![(x, y)];
+        while let Some((x, y)) = cells.pop() {
+            if !(min.0..max.0).contains(&x)
+                || !(min.1..max.1).contains(&y)
+                || map.tile(self.layer, x, y) != target
+                || !visited.insert((x, y))
+            {
+                continue;
+            }
+            edits.extend(self.edit(map, x, y, brush));
+            cells.extend([(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)]);
+        }
+        edits
+    }
+
+    /// Keeps `edits`, already applied to the map, in the current stroke.
+    pub fn record(&mut self, edits: Vec<Edit>) {
+        self.stroke.extend(edits);
+        if self.pressed.is_none() && !self.stroke.is_empty() {
+            self.undo.push(std::mem::take(&mut self.stroke));
+            self.redo.clear();
+        }
+    }
+
+    /// Edits reverting the last stroke, to apply to the map.
+    pub fn undo(&mut self) -> Vec<Edit> {
+        if self.pressed.is_some() {
+            return Vec::new();
+        }
+        let Some(stroke) = self.undo.pop() else {
+            return Vec::new();
+        };
+        let edits = stroke
+            .iter()
+            .rev()
+            .map(|edit| Edit {
+                old: edit.new,
+                new: edit.old,
+                ..*edit
+            })
+            .collect();
+        self.redo.push(stroke);
+        edits
+    }
+
+    /// Edits of the last stroke undone, to apply to the map again.
+    pub fn redo(&mut self) -> Vec<Edit> {
+        if self.pressed.is_some() {
+            return Vec::new();
+        }
+        let Some(stroke) = self.redo.pop() else {
+            return Vec::new();
+        };
+        let edits = stroke.clone();
+        self.undo.push(stroke);
+        edits
+    }
+
+    /// Saves the map as Tiled JSON, to the file it was loaded from when it's one.
+    pub fn save(&self, map: &super::map::Map) -> Result<(), super::map::MapError> {
+        super::map::save_map(map, &self.path)
+    }
+
+    /// Tool, layer and tile being used, shown in the window title.
+    pub fn status(&self, map: &super::map::Map) -> String {
+        let tileset = map
+            .tilesets
+            .get(self.tileset)
+            .map_or("", |tileset| tileset.name.as_str());
+        format!(
+            "Editor: {:?} on {}, tile {} of {}",
+            self.tool,
+            map.layer(self.layer).name(),
+            self.tile,
+            tileset
+        )
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        let palette = self
+            .palette
+            .as_ref()
+            .and_then(|palette| palette.render.as_ref());
+        if let Some(palette) = palette.filter(|_| self.enabled) {
+            palette.render.draw(render_pass);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Edit, Editor, Tool};
+    use crate::map::{Map, Tile};
+
+    const MAP: &str = "./resources/mapa.json";
+
+    fn load() -> (Map, Editor) {
+        let map = pollster::block_on(crate::map::load_map(MAP)).unwrap();
+        let editor = Editor::without_palette(&map, MAP);
+        (map, editor)
+    }
+
+    fn set_tiles(map: &mut Map, edits: &[Edit]) {
+        for edit in edits {
+            map.set_tile(edit.layer, edit.x, edit.y, edit.new);
+        }
+    }
+
+    /// Applies `edits` to `map` and records them, as the game does.
+    fn apply(map: &mut Map, editor: &mut Editor, edits: Vec<Edit>) {
+        set_tiles(map, &edits);
+        editor.record(edits);
+    }
+
+    fn tiles(map: &Map, layer: usize) -> Vec<Tile> {
+        (0..map.size.1 as i32)
+            .flat_map(|y| (0..map.size.0 as i32).map(move |x| map.tile(layer, x, y)))
+            .collect()
+    }
+
+    fn cells(edits: &[Edit]) -> std::collections::BTreeSet<(i32, i32)> {
+        edits.iter().map(|edit| (edit.x, edit.y)).collect()
+    }
+
+    /// A tile none of the cells of the edited layer have.
+    fn unused_tile(map: &Map, editor: &mut Editor) -> Tile {
+        let used = tiles(map, editor.layer);
+        editor.tile = (0..)
+            .find(|&id| used.iter().all(|tile| tile.gid != id + 1))
+            .unwrap();
+        editor.brush(map)
+    }
+
+    #[test]
+    fn saves_other_formats_next_to_them() {
+        assert_eq!(
+            super::save_path("./resources/mapa.json"),
+            "./resources/mapa.json"
+        );
+        assert_eq!(
+            super::save_path("./resources/mapa.tmx"),
+            "./resources/mapa.json"
+        );
+        assert_eq!(
+            super::save_path("./levels/world.ldtk"),
+            "./levels/world.json"
+        );
+    }
+
+    #[test]
+    fn undo_and_redo_a_whole_stroke() {
+        let (mut map, mut editor) = load();
+        let brush = unused_tile(&map, &mut editor);
+        let before = tiles(&map, editor.layer);
+
+        let edits = editor.press(&map, 2, 2);
+        apply(&mut map, &mut editor, edits);
+        for x in 3..6 {
+            let edits = editor.drag(&map, x, 2);
+            apply(&mut map, &mut editor, edits);
+        }
+        // Nothing to undo until the stroke ends
+        assert!(editor.undo().is_empty());
+        let edits = editor.release(&map, 5, 2);
+        apply(&mut map, &mut editor, edits);
+        let after = tiles(&map, editor.layer);
+        assert!((2..6).all(|x| map.tile(editor.layer, x, 2) == brush));
+
+        let edits = editor.undo();
+        assert_eq!(edits.len(), 4);
+        set_tiles(&mut map, &edits);
+        assert_eq!(tiles(&map, editor.layer), before);
+
+        let edits = editor.redo();
+        set_tiles(&mut map, &edits);
+        assert_eq!(tiles(&map, editor.layer), after);
+        assert!(editor.redo().is_empty());
+    }
+
+    #[test]
+    fn flood_fill_stays_in_its_region() {
+        let (mut map, mut editor) = load();
+        let layer = editor.layer;
+        let (wall, floor) = (
+            Tile {
+                gid: 1,
+                ..Default::default()
+            },
+            Tile::default(),
+        );
+        // A 3×3 room of floor inside walls, in a layer of walls
+        let (width, height) = (map.size.0 as i32, map.size.1 as i32);
+        for (x, y) in (0..height).flat_map(|y| (0..width).map(move |x| (x, y))) {
+            let tile = match (4..7).contains(&x) && (4..7).contains(&y) {
+                true => floor,
+                false => wall,
+            };
+            map.set_tile(layer, x, y, tile);
+        }
+        editor.tool = Tool::Fill;
+        let brush = unused_tile(&map, &mut editor);
+
+        let edits = editor.press(&map, 5, 5);
+        let room = (4..7).flat_map(|y| (4..7).map(move |x| (x, y))).collect();
+        assert_eq!(cells(&edits), room);
+        assert!(edits
+            .iter()
+            .all(|edit| edit.old == floor && edit.new == brush));
+        apply(&mut map, &mut editor, edits);
+        let edits = editor.release(&map, 5, 5);
+        assert!(edits.is_empty());
+
+        // The walls reach the sides of the layer, the fill stops there
+        let edits = editor.press(&map, 0, 0);
+        assert_eq!(edits.len(), (map.size.0 * map.size.1) as usize - 9);
+        assert!(edits.iter().all(|edit| {
+            (0..map.size.0 as i32).contains(&edit.x) && (0..map.size.1 as i32).contains(&edit.y)
+        }));
+    }
+
+    #[test]
+    fn boxes_fill_between_reversed_corners() {
+        let (map, mut editor) = load();
+        editor.tool = Tool::Box;
+        let brush = unused_tile(&map, &mut editor);
+
+        assert!(editor.press(&map, 5, 4).is_empty());
+        let edits = editor.release(&map, 2, 1);
+        let cells = cells(&edits);
+        assert_eq!(cells.len(), 16);
+        assert_eq!(cells.first(), Some(&(2, 1)));
+        assert_eq!(cells.last(), Some(&(5, 4)));
+        assert!(edits.iter().all(|edit| edit.new == brush));
+    }
+
+    #[test]
+    fn no_painting_under_the_palette() {
+        let (map, mut editor) = load();
+        unused_tile(&map, &mut editor);
+        let palette = nalgebra_glm::vec2(1.0, -1.0);
+        assert!(editor.palette_tile(&map, palette).is_some());
+
+        let (x, y) = map.world_to_cell(palette);
+        let edits = editor.press(&map, x, y);
+        assert_eq!(edits.len(), 1);
+        editor.record(edits);
+        assert!(editor.hover(&map, palette).is_empty());
+
+        let below = palette - nalgebra_glm::vec2(0.0, editor.palette_cell(&map).y * 5.0);
+        assert_eq!(editor.hover(&map, below).len(), 1);
+    }
+}
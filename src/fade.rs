@@ -0,0 +1,64 @@
+use wgpu::util::DeviceExt;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct FadeUniform {
+    color: [f32; 4],
+}
+
+/// Black overlay over the whole screen, faded in and out when switching maps.
+pub struct Fade {
+    render_pipeline: std::rc::Rc<wgpu::RenderPipeline>,
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    /// 0 when the screen is clear, 1 when it's black.
+    alpha: f32,
+}
+
+impl Fade {
+    pub fn new(
+        device: &wgpu::Device,
+        pipelines: &super::pipeline::Pipelines,
+        surface_format: &wgpu::TextureFormat,
+    ) -> Self {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Fade Buffer"),
+            contents: bytemuck::cast_slice(&[FadeUniform { color: [0.0; 4] }]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        Self {
+            render_pipeline: pipelines.get(
+                device,
+                super::pipeline::PipelineKey {
+                    shader: super::pipeline::Shader::Fade,
+                    blend: super::pipeline::BlendMode::Alpha,
+                    format: *surface_format,
+                },
+            ),
+            bind_group: pipelines.fade_bind_group(device, &buffer),
+            buffer,
+            alpha: 0.0,
+        }
+    }
+
+    pub fn set(&mut self, alpha: f32, queue: &wgpu::Queue) {
+        self.alpha = alpha.clamp(0.0, 1.0);
+        queue.write_buffer(
+            &self.buffer,
+            0,
+            bytemuck::cast_slice(&[FadeUniform {
+                color: [0.0, 0.0, 0.0, self.alpha],
+            }]),
+        );
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if self.alpha <= 0.0 {
+            return;
+        }
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,185 @@
+use wgpu::util::DeviceExt;
+
+/// Pipeline shared by the renders of the image layers of a map.
+pub struct ImageLayerRenderer {
+    render_pipeline: std::rc::Rc<wgpu::RenderPipeline>,
+    pipelines: std::rc::Rc<super::pipeline::Pipelines>,
+    camera_bind_group: std::rc::Rc<wgpu::BindGroup>,
+}
+
+impl ImageLayerRenderer {
+    pub fn new(
+        device: &wgpu::Device,
+        pipelines: std::rc::Rc<super::pipeline::Pipelines>,
+        camera_bind_group: std::rc::Rc<wgpu::BindGroup>,
+        surface_format: &wgpu::TextureFormat,
+    ) -> Self {
+        Self {
+            render_pipeline: pipelines.get(
+                device,
+                super::pipeline::PipelineKey {
+                    shader: super::pipeline::Shader::Image,
+                    blend: super::pipeline::BlendMode::Alpha,
+                    format: *surface_format,
+                },
+            ),
+            pipelines,
+            camera_bind_group,
+        }
+    }
+
+    /// Render of the image layer `layer`, `None` when it has no image.
+    pub fn create_render(
+        &self,
+        layer: usize,
+        map: &super::map::Map,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<Option<ImageLayerRender>, super::map::MapError> {
+        let super::map::Layers::ImageLayer {
+            image,
+            image_path,
+            repeat,
+            ..
+        } = map.layer(layer)
+        else {
+            return Ok(None);
+        };
+        if image.is_empty() {
+            return Ok(None);
+        }
+
+        let texture =
+            super::texture::Texture::from_bytes(device, queue, image, map.layer(layer).name())
+                .map_err(|error| super::map::MapError::Image {
+                    path: image_path.clone(),
+                    message: error.to_string(),
+                })?;
+        let address_mode = |repeat: bool| match repeat {
+            true => wgpu::AddressMode::Repeat,
+            false => wgpu::AddressMode::ClampToEdge,
+        };
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: address_mode(repeat.0),
+            address_mode_v: address_mode(repeat.1),
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let texture_bind_group = self
+            .pipelines
+            .texture_bind_group(device, &texture.view, &sampler);
+
+        let layer_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Layer Buffer"),
+            contents: bytemuck::cast_slice(&[super::map::LayerUniform::new(
+                map,
+                layer,
+                nalgebra_glm::vec2(0.0, 0.0),
+            )]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let layer_bind_group = self.pipelines.layer_bind_group(device, &layer_buffer);
+
+        // The quad is rewritten in `ImageLayerRender::update`
+        let (vertex_points, vertex_indices) =
+            super::vertex::get_rect(nalgebra_glm::vec3(0.0, 0.0, 0.0));
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Image Layer Vertex Buffer"),
+            contents: bytemuck::cast_slice(&vertex_points),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&vertex_indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        Ok(Some(ImageLayerRender {
+            layer,
+            render: super::render::Render {
+                vertex_buffer,
+                index_buffer,
+                render_pipeline: self.render_pipeline.clone(),
+                index_count: vertex_indices.len() as _,
+                transform_buffer: None,
+                bind_groups: vec![
+                    (0, texture_bind_group),
+                    (1, self.camera_bind_group.clone()),
+                    (2, layer_bind_group),
+                ],
+                instances: 1,
+            },
+            layer_buffer,
+        }))
+    }
+}
+
+/// A quad textured with the image of an image layer.
+pub struct ImageLayerRender {
+    pub layer: usize,
+    render: super::render::Render,
+    layer_buffer: wgpu::Buffer,
+}
+
+impl ImageLayerRender {
+    /// Moves the layer with its offset and parallax. The quad covers the image,
+    /// stretched over `view` (in world units) along the axes the image repeats on.
+    pub fn update(
+        &mut self,
+        view: &parry2d::bounding_volume::Aabb,
+        map: &super::map::Map,
+        queue: &wgpu::Queue,
+    ) {
+        let super::map::Layers::ImageLayer {
+            image_size, repeat, ..
+        } = map.layer(self.layer)
+        else {
+            return;
+        };
+
+        let camera = nalgebra_glm::vec2(view.center().x, view.center().y);
+        queue.write_buffer(
+            &self.layer_buffer,
+            0,
+            bytemuck::cast_slice(&[super::map::LayerUniform::new(map, self.layer, camera)]),
+        );
+
+        // Images are placed at the top-left of the map
+        let top_left = map.to_world((0.0, 0.0));
+        let size = nalgebra_glm::vec2(image_size.0 as f32 * 2.0, image_size.1 as f32 * 2.0);
+        let shift = map.layer_shift(self.layer, camera);
+        let (mut min, mut max) = (
+            nalgebra_glm::vec2(top_left.x, top_left.y - size.y),
+            nalgebra_glm::vec2(top_left.x + size.x, top_left.y),
+        );
+        if repeat.0 {
+            (min.x, max.x) = (view.mins.x - shift.x, view.maxs.x - shift.x);
+        }
+        if repeat.1 {
+            (min.y, max.y) = (view.mins.y - shift.y, view.maxs.y - shift.y);
+        }
+
+        let vertex = |x: f32, y: f32| {
+            super::vertex::Vertex::new(
+                [x, y, 0.0],
+                [(x - top_left.x) / size.x, (top_left.y - y) / size.y],
+            )
+        };
+        queue.write_buffer(
+            &self.render.vertex_buffer,
+            0,
+            bytemuck::cast_slice(&[
+                vertex(max.x, max.y),
+                vertex(min.x, max.y),
+                vertex(min.x, min.y),
+                vertex(max.x, min.y),
+            ]),
+        );
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        self.render.draw(render_pass);
+    }
+}
@@ -1,5 +1,13 @@
+mod atlas;
+mod batch;
 mod camera;
+mod chunk;
+mod collision;
+mod editor;
+mod fade;
+mod image_layer;
 mod map;
+mod pipeline;
 mod render;
 mod texture;
 mod transform;
@@ -13,8 +21,6 @@ use winit::{
 
 use std::time::Duration;
 
-use parry2d::bounding_volume::BoundingVolume;
-
 #[cfg(not(target_arch = "wasm32"))]
 use std::time::Instant;
 #[cfg(target_arch = "wasm32")]
@@ -23,10 +29,47 @@ use wasm_timer::Instant;
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 
-use wgpu::util::DeviceExt;
-
 use winit::window::Window;
 
+/// Tile layer whose non-empty cells block the player, drawn only when debugging collisions.
+/// A tile layer with a `collision` bool property set takes precedence.
+const COLLISION_LAYER: &str = "colisiones";
+
+/// Bool tile property marking tiles that block the player on any visible layer.
+const SOLID_PROPERTY: &str = "solid";
+
+/// Object the player starts at.
+const SPAWN_POINT: &str = "spawn_point";
+
+/// Window title, replaced by the editor status while editing.
+const TITLE: &str = "idk";
+
+/// Pixel size of the sprites of `fullspritesheet.png`.
+const CHARACTER_SPRITE_SIZE: (u32, u32) = (96, 64);
+
+/// Largest width and height of the sprite atlas pages, what WebGL supports.
+const ATLAS_SIZE: u32 = 2048;
+
+/// A visible layer of the map, drawn in the order Tiled does.
+enum MapLayer {
+    Tiles(chunk::ChunkStreamer),
+    Image(Box<image_layer::ImageLayerRender>),
+}
+
+/// Seed given as `--seed <seed>`, to play a generated map instead of `mapa.json`.
+fn map_seed() -> Option<u64> {
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "wasm32")] {
+            None
+        } else {
+            let args = std::env::args().collect::<Vec<_>>();
+            args.windows(2)
+                .find(|args| args[0] == "--seed")
+                .and_then(|args| args[1].parse().ok())
+        }
+    }
+}
+
 #[derive(Debug)]
 struct Animation {
     index: i32,
@@ -48,6 +91,7 @@ pub async fn run() {
 
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new()
+        .with_title(TITLE)
         .with_inner_size(winit::dpi::LogicalSize::new(1280, 720))
         .build(&event_loop)
         .unwrap();
@@ -71,7 +115,21 @@ pub async fn run() {
             .expect("Couldn't append canvas to document body.");
     }
 
-    let mut state = State::new(window).await;
+    let mut state = match State::new(window).await {
+        Ok(state) => state,
+        Err(error) => {
+            log::error!("Couldn't load the map: {}", error);
+
+            #[cfg(target_arch = "wasm32")]
+            web_sys::window()
+                .and_then(|win| win.document())
+                .and_then(|doc| doc.get_element_by_id("wasm-example"))
+                .expect("Couldn't find the wasm-example element.")
+                .set_text_content(Some(&format!("Couldn't load the map: {}", error)));
+
+            return;
+        }
+    };
 
     let mut previous_frame = Instant::now();
 
@@ -111,92 +169,100 @@ pub async fn run() {
         Event::WindowEvent {
             ref event,
             window_id,
-        } if window_id == state.window().id() => {
-            if !state.input(event) {
-                match event {
-                    WindowEvent::CursorMoved { position, .. } => {
-                        state.mouse_pos =
-                            nalgebra_glm::vec3(position.x as f32, position.y as f32, 0.0);
-                    }
-                    WindowEvent::CloseRequested
-                    | WindowEvent::KeyboardInput {
-                        input:
-                            KeyboardInput {
-                                state: ElementState::Pressed,
-                                virtual_keycode: Some(VirtualKeyCode::Escape),
-                                ..
-                            },
-                        ..
-                    } => *control_flow = ControlFlow::Exit,
-                    WindowEvent::KeyboardInput {
-                        input:
-                            KeyboardInput {
-                                state: ElementState::Pressed,
-                                virtual_keycode: Some(key),
-                                ..
-                            },
+        } if window_id == state.window().id() && !state.input(event) => match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                state.mouse_pos = nalgebra_glm::vec3(position.x as f32, position.y as f32, 0.0);
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } => {
+                if let Some((x, y)) = state.map.pick_cell(state.mouse_world_position()) {
+                    log::info!("Picked cell ({}, {})", x, y);
+                }
+            }
+            WindowEvent::CloseRequested
+            | WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::Escape),
                         ..
-                    } => match key {
-                        VirtualKeyCode::C => state.collision.2 = !state.collision.2,
-                        VirtualKeyCode::W => state.actions[0] = true,
-                        VirtualKeyCode::S => state.actions[2] = true,
-                        VirtualKeyCode::A => state.actions[1] = true,
-                        VirtualKeyCode::D => state.actions[3] = true,
-                        VirtualKeyCode::Q => state.actions[4] = true,
-                        VirtualKeyCode::E => state.actions[5] = true,
-                        VirtualKeyCode::R => {
-                            state.zoom = if state.zoom > 0.0 {
-                                state.zoom - 0.1
-                            } else {
-                                0.0
-                            }
-                        }
-                        VirtualKeyCode::F => {
-                            state.zoom += 0.1;
-                        }
-                        VirtualKeyCode::LShift => state.actions[6] = true,
-                        _ => {}
                     },
-                    WindowEvent::KeyboardInput {
-                        input:
-                            KeyboardInput {
-                                state: ElementState::Released,
-                                virtual_keycode: Some(key),
-                                ..
-                            },
+                ..
+            } => *control_flow = ControlFlow::Exit,
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(key),
                         ..
-                    } => match key {
-                        VirtualKeyCode::W => {
-                            state.actions[0] = false;
-                        }
-                        VirtualKeyCode::S => {
-                            state.actions[2] = false;
-                        }
-                        VirtualKeyCode::A => {
-                            state.actions[1] = false;
-                        }
-                        VirtualKeyCode::D => {
-                            state.actions[3] = false;
-                        }
-                        VirtualKeyCode::Q => {
-                            state.actions[4] = false;
-                        }
-                        VirtualKeyCode::E => {
-                            state.actions[5] = false;
-                        }
-                        VirtualKeyCode::LShift => state.actions[6] = false,
-                        _ => {}
                     },
-                    WindowEvent::Resized(physical_size) => {
-                        state.resize(*physical_size);
-                    }
-                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                        state.resize(**new_inner_size);
+                ..
+            } => match key {
+                VirtualKeyCode::C => state.collision.1 = !state.collision.1,
+                VirtualKeyCode::Tab => {
+                    state.editor.enabled = !state.editor.enabled;
+                    state.show_editor_status();
+                }
+                VirtualKeyCode::W => state.actions[0] = true,
+                VirtualKeyCode::S => state.actions[2] = true,
+                VirtualKeyCode::A => state.actions[1] = true,
+                VirtualKeyCode::D => state.actions[3] = true,
+                VirtualKeyCode::Q => state.actions[4] = true,
+                VirtualKeyCode::E => state.actions[5] = true,
+                VirtualKeyCode::R => {
+                    state.zoom = if state.zoom > 0.0 {
+                        state.zoom - 0.1
+                    } else {
+                        0.0
                     }
-                    _ => {}
                 }
+                VirtualKeyCode::F => {
+                    state.zoom += 0.1;
+                }
+                VirtualKeyCode::LShift => state.actions[6] = true,
+                _ => {}
+            },
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Released,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } => match key {
+                VirtualKeyCode::W => {
+                    state.actions[0] = false;
+                }
+                VirtualKeyCode::S => {
+                    state.actions[2] = false;
+                }
+                VirtualKeyCode::A => {
+                    state.actions[1] = false;
+                }
+                VirtualKeyCode::D => {
+                    state.actions[3] = false;
+                }
+                VirtualKeyCode::Q => {
+                    state.actions[4] = false;
+                }
+                VirtualKeyCode::E => {
+                    state.actions[5] = false;
+                }
+                VirtualKeyCode::LShift => state.actions[6] = false,
+                _ => {}
+            },
+            WindowEvent::Resized(physical_size) => {
+                state.resize(*physical_size);
             }
-        }
+            WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                state.resize(**new_inner_size);
+            }
+            _ => {}
+        },
         _ => {}
     });
 }
@@ -217,7 +283,7 @@ struct State {
     // uniform_buffer: wgpu::Buffer,
     time_since_last_frame: Duration,
     camera_buffer: std::rc::Rc<wgpu::Buffer>,
-    // camera_bind_group: std::rc::Rc<wgpu::BindGroup>,
+    camera_bind_group: std::rc::Rc<wgpu::BindGroup>,
     camera: (nalgebra_glm::Mat4, nalgebra_glm::Mat4),
     camera_uniform: camera::CameraUniform,
     // transform_buffer: wgpu::Buffer,
@@ -230,16 +296,181 @@ struct State {
     zoom: f32,
     // indices: Vec<u16>,
     mouse_pos: nalgebra_glm::Vec3,
-    renders: Vec<render::Render>,
+    map: map::Map,
+    tile_renderer: map::TileRenderer,
+    map_layers: Vec<MapLayer>,
+    collision_layer: Option<chunk::ChunkStreamer>,
+    /// Time the map has been running, drives the animated tiles.
+    map_time: Duration,
+    sprites: batch::SpriteBatch,
+    /// Frames of `fullspritesheet.png`, by `Transform::index`.
+    character_sprites: Vec<batch::Sprite>,
     collision: (
-        Vec<transform::Transform>,
-        Vec<parry2d::bounding_volume::Aabb>,
+        std::collections::HashMap<LayerCell, collision::Collider>,
         bool,
     ),
+    editor: editor::Editor,
+    modifiers: ModifiersState,
+    /// Kept to build the renders of the maps switched to.
+    pipelines: std::rc::Rc<pipeline::Pipelines>,
+    /// Maps other than `map`, reachable through portals.
+    world: map::World,
+    portals: Vec<(map::Portal, parry2d::bounding_volume::Aabb)>,
+    /// Whether the player was on a portal, they go through it only once they step in.
+    in_portal: bool,
+    transition: Option<Transition>,
+    fade: fade::Fade,
+}
+
+/// Going through a portal: the screen fades out, the map is switched and it fades back in.
+struct Transition {
+    portal: map::Portal,
+    time: Duration,
+    switched: bool,
+}
+
+impl Transition {
+    /// Advances the transition `delta_time`. Returns whether the map is to be
+    /// switched now and the alpha of the fade, none once the transition is over.
+    /// Portals without fade switch maps and end at once.
+    fn update(&mut self, delta_time: Duration) -> (bool, Option<f32>) {
+        self.time += delta_time;
+        let fade = self.portal.fade;
+        let switch = !self.switched && self.time >= fade;
+        self.switched |= switch;
+        if fade.is_zero() {
+            return (switch, None);
+        }
+
+        let progress = self.time.as_secs_f32() / fade.as_secs_f32();
+        (
+            switch,
+            (progress < 2.0).then(|| 1.0 - (progress - 1.0).abs()),
+        )
+    }
+}
+
+/// Renders and colliders of the map being played, built again when it changes.
+struct MapRenders {
+    tile_renderer: map::TileRenderer,
+    map_layers: Vec<MapLayer>,
+    collision_layer: Option<chunk::ChunkStreamer>,
+    colliders: std::collections::HashMap<LayerCell, collision::Collider>,
+}
+
+impl MapRenders {
+    fn new(
+        map: &map::Map,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pipelines: &std::rc::Rc<pipeline::Pipelines>,
+        camera_bind_group: &std::rc::Rc<wgpu::BindGroup>,
+        surface_format: &wgpu::TextureFormat,
+    ) -> Result<Self, map::MapError> {
+        let tile_renderer = map::TileRenderer::new(
+            map,
+            device,
+            queue,
+            pipelines.clone(),
+            camera_bind_group.clone(),
+            surface_format,
+        )?;
+
+        let collision_layer_id = collision_layer(map);
+        let image_layer_renderer = image_layer::ImageLayerRenderer::new(
+            device,
+            pipelines.clone(),
+            camera_bind_group.clone(),
+            surface_format,
+        );
+        let map_layers = map
+            .all_layers()
+            .into_iter()
+            .enumerate()
+            .filter(|&(id, _)| map.is_layer_visible(id) && Some(id) != collision_layer_id)
+            .filter_map(|(id, layer)| match layer {
+                map::Layers::TileLayer { .. } => Some(Ok(MapLayer::Tiles(
+                    chunk::ChunkStreamer::new(id, map, &tile_renderer, device),
+                ))),
+                map::Layers::ImageLayer { .. } => image_layer_renderer
+                    .create_render(id, map, device, queue)
+                    .transpose()
+                    .map(|render| render.map(|render| MapLayer::Image(Box::new(render)))),
+                _ => None,
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let collision_layer =
+            collision_layer_id.map(|id| chunk::ChunkStreamer::new(id, map, &tile_renderer, device));
+
+        let mut colliders = std::collections::HashMap::new();
+        for id in map.tile_layers() {
+            for (position, size) in map.chunk_bounds(id, 16) {
+                for y in position.1..position.1 + size.1 as i32 {
+                    for x in position.0..position.0 + size.0 as i32 {
+                        if let Some(collider) = solid_cell(map, collision_layer_id, id, x, y) {
+                            colliders.insert((id, x, y), collider);
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(Self {
+            tile_renderer,
+            map_layers,
+            collision_layer,
+            colliders,
+        })
+    }
+}
+
+/// The first tile layer with the `collision` property set, or the one named
+/// `colisiones` when none has it.
+fn collision_layer(map: &map::Map) -> Option<usize> {
+    map.tile_layers()
+        .find(|&id| {
+            map.layer(id)
+                .properties()
+                .get("collision")
+                .and_then(map::PropertyValue::as_bool)
+                .unwrap_or(false)
+        })
+        .or_else(|| map.find_layer(COLLISION_LAYER))
+}
+
+/// A tile layer and a cell (x, y) in it.
+type LayerCell = (usize, i32, i32);
+
+/// Collider of the cell (x, y) of the tile layer `id` when it blocks the player.
+/// Every cell of the collision layer blocks, on the other visible layers only
+/// solid tiles do.
+fn solid_cell(
+    map: &map::Map,
+    collision_layer: Option<usize>,
+    id: usize,
+    x: i32,
+    y: i32,
+) -> Option<collision::Collider> {
+    let tile = map.tile(id, x, y);
+    let is_solid = match Some(id) == collision_layer {
+        true => !tile.is_empty(),
+        false => {
+            map.is_layer_visible(id)
+                && map
+                    .tile_properties(tile.gid)
+                    .and_then(|properties| properties.get(SOLID_PROPERTY))
+                    .and_then(map::PropertyValue::as_bool)
+                    .unwrap_or(false)
+        }
+    };
+    if !is_solid {
+        return None;
+    }
+    collision::Collider::from_tile(map, tile, x, y)
 }
 
 impl State {
-    async fn new(window: Window) -> Self {
+    async fn new(window: Window) -> Result<Self, map::MapError> {
         let size = window.inner_size();
 
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
@@ -333,24 +564,11 @@ impl State {
             },
         ));
 
-        let camera_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: None,
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
-            });
+        let pipelines = std::rc::Rc::new(pipeline::Pipelines::new(&device));
 
         let camera_bind_group =
             std::rc::Rc::new(device.create_bind_group(&wgpu::BindGroupDescriptor {
-                layout: &camera_bind_group_layout,
+                layout: &pipelines.camera_bind_group_layout,
                 entries: &[wgpu::BindGroupEntry {
                     binding: 0,
                     resource: camera_buffer.as_entire_binding(),
@@ -358,14 +576,6 @@ impl State {
                 label: Some("Camera Bind Group"),
             }));
 
-        let transform = {
-            let mut t = transform::Transform::new();
-            t.label = Some("transform".to_string());
-            t.translate(&nalgebra_glm::vec3(250.0, 200.0, 0.0));
-            // t.rotate(&nalgebra_glm::vec3(0.0, 0.0, -45.0));
-            std::rc::Rc::new(std::cell::RefCell::new(t))
-        };
-
         let instances = (0..10)
             .flat_map(|y: i32| {
                 (0..10).map(move |x| {
@@ -376,463 +586,95 @@ impl State {
                         0.0,
                     ));
                     t.label = Some(format!("{}", x + (y * 10)).to_string());
+                    // Half the size of the player
+                    t.scale(&nalgebra_glm::vec3(0.5, 0.5, 1.0));
                     // t.rotate(&nalgebra_glm::vec3(0.0, 0.0, 45.0));
                     std::rc::Rc::new(std::cell::RefCell::new(t))
                 })
             })
             .collect::<Vec<_>>();
 
-        let map = map::load_map("./resources/mapa.json").await;
-
-        let renders = vec![
-            // MAP LAYER 0
-            map::generate_render(
-                0,
-                &map,
-                &device,
-                &queue,
-                &camera_bind_group_layout,
-                camera_bind_group.clone(),
-                &surface_format,
-            ),
-            // MAP LAYER 1
-            map::generate_render(
-                1,
-                &map,
-                &device,
-                &queue,
-                &camera_bind_group_layout,
-                camera_bind_group.clone(),
-                &surface_format,
-            ),
-            // MAP LAYER 2
-            map::generate_render(
-                2,
-                &map,
-                &device,
-                &queue,
-                &camera_bind_group_layout,
-                camera_bind_group.clone(),
-                &surface_format,
-            ),
-            // MAP LAYER 4 (COLLISION)
-            map::generate_render(
-                3,
-                &map,
-                &device,
-                &queue,
-                &camera_bind_group_layout,
-                camera_bind_group.clone(),
-                &surface_format,
-            ),
-            // TRANSFORM
-            {
-                let diffuse_bytes = include_bytes!("../resources/fullspritesheet.png");
-
-                let diffuse_texture = texture::Texture::from_bytes(
-                    &device,
-                    &queue,
-                    diffuse_bytes,
-                    "fullspritesheet.png",
-                )
-                .unwrap();
-                let texture_bind_group_layout: wgpu::BindGroupLayout = device
-                    .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                        entries: &[
-                            wgpu::BindGroupLayoutEntry {
-                                binding: 0,
-                                visibility: wgpu::ShaderStages::FRAGMENT,
-                                ty: wgpu::BindingType::Texture {
-                                    multisampled: false,
-                                    view_dimension: wgpu::TextureViewDimension::D2,
-                                    sample_type: wgpu::TextureSampleType::Float {
-                                        filterable: true,
-                                    },
-                                },
-                                count: None,
-                            },
-                            wgpu::BindGroupLayoutEntry {
-                                binding: 1,
-                                visibility: wgpu::ShaderStages::FRAGMENT,
-                                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                                count: None,
-                            },
-                        ],
-                        label: Some("texture_bind_group_layout"),
-                    });
-                let texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-                    layout: &texture_bind_group_layout,
-                    entries: &[
-                        wgpu::BindGroupEntry {
-                            binding: 0,
-                            resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 1,
-                            resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                        },
-                    ],
-                    label: Some("diffuse_bind_group"),
-                });
-
-                let (vertex_points, vertex_indices) =
-                    vertex::get_rect(nalgebra_glm::vec3(96.0, 64.0, 0.0));
-                let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: None,
-                    contents: bytemuck::cast_slice(&vertex_points),
-                    usage: wgpu::BufferUsages::VERTEX,
-                });
-                let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: None,
-                    contents: bytemuck::cast_slice(&vertex_indices),
-                    usage: wgpu::BufferUsages::INDEX,
-                });
-
-                let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-                    label: None,
-                    source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(include_str!(
-                        "shaders/main.wgsl"
-                    ))),
-                });
-
-                let render_pipeline_layout =
-                    device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                        label: Some("Render Pipeline Layout"),
-                        bind_group_layouts: &[
-                            &texture_bind_group_layout,
-                            // &uniform_bind_group_layout,
-                            &camera_bind_group_layout,
-                        ],
-                        push_constant_ranges: &[],
-                    });
-
-                let render_pipeline = std::rc::Rc::new(device.create_render_pipeline(
-                    &wgpu::RenderPipelineDescriptor {
-                        label: None,
-                        layout: Some(&render_pipeline_layout),
-                        vertex: wgpu::VertexState {
-                            module: &shader,
-                            entry_point: "vs_main",
-                            buffers: &[
-                                wgpu::VertexBufferLayout {
-                                    array_stride: std::mem::size_of::<vertex::Vertex>()
-                                        as wgpu::BufferAddress,
-                                    step_mode: wgpu::VertexStepMode::Vertex,
-                                    attributes: &[
-                                        wgpu::VertexAttribute {
-                                            offset: 0,
-                                            shader_location: 0,
-                                            format: wgpu::VertexFormat::Float32x3,
-                                        },
-                                        wgpu::VertexAttribute {
-                                            offset: std::mem::size_of::<[f32; 3]>()
-                                                as wgpu::BufferAddress,
-                                            shader_location: 1,
-                                            format: wgpu::VertexFormat::Float32x2,
-                                        },
-                                    ],
-                                },
-                                wgpu::VertexBufferLayout {
-                                    array_stride: std::mem::size_of::<transform::TransformRaw>()
-                                        as wgpu::BufferAddress,
-                                    step_mode: wgpu::VertexStepMode::Instance,
-                                    attributes: &[
-                                        wgpu::VertexAttribute {
-                                            offset: 0,
-                                            shader_location: 5,
-                                            format: wgpu::VertexFormat::Float32x4,
-                                        },
-                                        wgpu::VertexAttribute {
-                                            offset: std::mem::size_of::<[f32; 4]>()
-                                                as wgpu::BufferAddress,
-                                            shader_location: 6,
-                                            format: wgpu::VertexFormat::Float32x4,
-                                        },
-                                        wgpu::VertexAttribute {
-                                            offset: std::mem::size_of::<[f32; 8]>()
-                                                as wgpu::BufferAddress,
-                                            shader_location: 7,
-                                            format: wgpu::VertexFormat::Float32x4,
-                                        },
-                                        wgpu::VertexAttribute {
-                                            offset: std::mem::size_of::<[f32; 12]>()
-                                                as wgpu::BufferAddress,
-                                            shader_location: 8,
-                                            format: wgpu::VertexFormat::Float32x4,
-                                        },
-                                        wgpu::VertexAttribute {
-                                            offset: std::mem::size_of::<[f32; 16]>()
-                                                as wgpu::BufferAddress,
-                                            shader_location: 9,
-                                            format: wgpu::VertexFormat::Sint32,
-                                        },
-                                        wgpu::VertexAttribute {
-                                            offset: std::mem::size_of::<[f32; 17]>()
-                                                as wgpu::BufferAddress,
-                                            shader_location: 10,
-                                            format: wgpu::VertexFormat::Sint32,
-                                        },
-                                    ],
-                                },
-                            ],
-                        },
-                        fragment: Some(wgpu::FragmentState {
-                            module: &shader,
-                            entry_point: "fs_main",
-                            targets: &[Some(wgpu::ColorTargetState {
-                                format: surface_format,
-                                blend: Some(wgpu::BlendState {
-                                    color: wgpu::BlendComponent::REPLACE,
-                                    alpha: wgpu::BlendComponent::OVER,
-                                }),
-                                write_mask: wgpu::ColorWrites::all(),
-                            })],
-                        }),
-                        primitive: wgpu::PrimitiveState {
-                            topology: wgpu::PrimitiveTopology::TriangleList,
-                            strip_index_format: None,
-                            front_face: wgpu::FrontFace::Ccw,
-                            cull_mode: Some(wgpu::Face::Back),
-                            unclipped_depth: false,
-                            polygon_mode: wgpu::PolygonMode::Fill,
-                            conservative: false,
-                        },
-                        depth_stencil: None,
-                        multisample: wgpu::MultisampleState {
-                            count: 1,
-                            mask: !0,
-                            alpha_to_coverage_enabled: false,
-                        },
-                        multiview: None,
-                    },
-                ));
-
-                render::Render {
-                    vertex_buffer,
-                    index_buffer,
-                    render_pipeline,
-                    index_count: vertex_indices.len() as _,
-                    transform_buffer: Some(std::rc::Rc::new(device.create_buffer_init(
-                        &wgpu::util::BufferInitDescriptor {
-                            label: Some("Transform Buffer"),
-                            contents: bytemuck::cast_slice(&[transform.as_ref().borrow().to_raw()]),
-                            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-                        },
-                    ))),
-                    bind_groups: vec![
-                        (0, std::rc::Rc::new(texture_bind_group)),
-                        (1, camera_bind_group.clone()),
-                    ],
-                    instances: 1,
+        // The editor saves generated maps next to mapa.json instead of over it
+        let (world, map) = match map_seed() {
+            Some(seed) => {
+                log::info!("Generating a map with seed {}", seed);
+                let map = map::Generator {
+                    seed,
+                    ..Default::default()
                 }
-            },
-            // INSTANCES
-            {
-                let diffuse_bytes = include_bytes!("../resources/fullspritesheet.png");
-                let diffuse_texture = texture::Texture::from_bytes(
-                    &device,
-                    &queue,
-                    diffuse_bytes,
-                    "fullspritesheet.png",
+                .generate()
+                .await?;
+                (
+                    map::World::new(&format!("./resources/island_{}.json", seed)),
+                    map,
                 )
-                .unwrap();
-                let texture_bind_group_layout: wgpu::BindGroupLayout = device
-                    .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                        entries: &[
-                            wgpu::BindGroupLayoutEntry {
-                                binding: 0,
-                                visibility: wgpu::ShaderStages::FRAGMENT,
-                                ty: wgpu::BindingType::Texture {
-                                    multisampled: false,
-                                    view_dimension: wgpu::TextureViewDimension::D2,
-                                    sample_type: wgpu::TextureSampleType::Float {
-                                        filterable: true,
-                                    },
-                                },
-                                count: None,
-                            },
-                            wgpu::BindGroupLayoutEntry {
-                                binding: 1,
-                                visibility: wgpu::ShaderStages::FRAGMENT,
-                                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                                count: None,
-                            },
-                        ],
-                        label: Some("texture_bind_group_layout"),
-                    });
-                let texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-                    layout: &texture_bind_group_layout,
-                    entries: &[
-                        wgpu::BindGroupEntry {
-                            binding: 0,
-                            resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 1,
-                            resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                        },
-                    ],
-                    label: Some("diffuse_bind_group"),
-                });
+            }
+            None => map::World::load("./resources/mapa.json").await?,
+        };
 
-                let instance_data = instances
-                    .iter()
-                    .map(|x| x.as_ref().borrow().to_raw())
-                    .collect::<Vec<_>>();
-
-                let instance_buffer =
-                    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                        label: Some("Instance Buffer"),
-                        contents: bytemuck::cast_slice(&instance_data),
-                        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-                    });
-
-                let (vertex_points, vertex_indices) =
-                    vertex::get_rect(nalgebra_glm::vec3(48.0, 32.0, 0.0));
-                let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: None,
-                    contents: bytemuck::cast_slice(&vertex_points),
-                    usage: wgpu::BufferUsages::VERTEX,
-                });
-                let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: None,
-                    contents: bytemuck::cast_slice(&vertex_indices),
-                    usage: wgpu::BufferUsages::INDEX,
-                });
+        let transform = {
+            let spawn_point = map
+                .find_object(SPAWN_POINT)
+                .map(|spawn_point| map.object_to_world(spawn_point.center()))
+                .unwrap_or(nalgebra_glm::vec2(250.0, 200.0));
 
-                let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-                    label: None,
-                    source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(include_str!(
-                        "shaders/main.wgsl"
-                    ))),
-                });
+            let mut t = transform::Transform::new();
+            t.label = Some("transform".to_string());
+            t.translate(&nalgebra_glm::vec3(spawn_point.x, spawn_point.y, 0.0));
+            // t.rotate(&nalgebra_glm::vec3(0.0, 0.0, -45.0));
+            std::rc::Rc::new(std::cell::RefCell::new(t))
+        };
 
-                let render_pipeline_layout =
-                    device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                        label: Some("Render Pipeline Layout"),
-                        bind_group_layouts: &[
-                            &texture_bind_group_layout,
-                            // &uniform_bind_group_layout,
-                            &camera_bind_group_layout,
-                        ],
-                        push_constant_ranges: &[],
-                    });
-
-                let render_pipeline = std::rc::Rc::new(device.create_render_pipeline(
-                    &wgpu::RenderPipelineDescriptor {
-                        label: None,
-                        layout: Some(&render_pipeline_layout),
-                        vertex: wgpu::VertexState {
-                            module: &shader,
-                            entry_point: "vs_main",
-                            buffers: &[
-                                wgpu::VertexBufferLayout {
-                                    array_stride: std::mem::size_of::<vertex::Vertex>()
-                                        as wgpu::BufferAddress,
-                                    step_mode: wgpu::VertexStepMode::Vertex,
-                                    attributes: &[
-                                        wgpu::VertexAttribute {
-                                            offset: 0,
-                                            shader_location: 0,
-                                            format: wgpu::VertexFormat::Float32x3,
-                                        },
-                                        wgpu::VertexAttribute {
-                                            offset: std::mem::size_of::<[f32; 3]>()
-                                                as wgpu::BufferAddress,
-                                            shader_location: 1,
-                                            format: wgpu::VertexFormat::Float32x2,
-                                        },
-                                    ],
-                                },
-                                wgpu::VertexBufferLayout {
-                                    array_stride: std::mem::size_of::<transform::TransformRaw>()
-                                        as wgpu::BufferAddress,
-                                    step_mode: wgpu::VertexStepMode::Instance,
-                                    attributes: &[
-                                        wgpu::VertexAttribute {
-                                            offset: 0,
-                                            shader_location: 5,
-                                            format: wgpu::VertexFormat::Float32x4,
-                                        },
-                                        wgpu::VertexAttribute {
-                                            offset: std::mem::size_of::<[f32; 4]>()
-                                                as wgpu::BufferAddress,
-                                            shader_location: 6,
-                                            format: wgpu::VertexFormat::Float32x4,
-                                        },
-                                        wgpu::VertexAttribute {
-                                            offset: std::mem::size_of::<[f32; 8]>()
-                                                as wgpu::BufferAddress,
-                                            shader_location: 7,
-                                            format: wgpu::VertexFormat::Float32x4,
-                                        },
-                                        wgpu::VertexAttribute {
-                                            offset: std::mem::size_of::<[f32; 12]>()
-                                                as wgpu::BufferAddress,
-                                            shader_location: 8,
-                                            format: wgpu::VertexFormat::Float32x4,
-                                        },
-                                        wgpu::VertexAttribute {
-                                            offset: std::mem::size_of::<[f32; 16]>()
-                                                as wgpu::BufferAddress,
-                                            shader_location: 9,
-                                            format: wgpu::VertexFormat::Sint32,
-                                        },
-                                        wgpu::VertexAttribute {
-                                            offset: std::mem::size_of::<[f32; 17]>()
-                                                as wgpu::BufferAddress,
-                                            shader_location: 10,
-                                            format: wgpu::VertexFormat::Sint32,
-                                        },
-                                    ],
-                                },
-                            ],
-                        },
-                        fragment: Some(wgpu::FragmentState {
-                            module: &shader,
-                            entry_point: "fs_main",
-                            targets: &[Some(wgpu::ColorTargetState {
-                                format: surface_format,
-                                blend: Some(wgpu::BlendState {
-                                    color: wgpu::BlendComponent::REPLACE,
-                                    alpha: wgpu::BlendComponent::OVER,
-                                }),
-                                write_mask: wgpu::ColorWrites::all(),
-                            })],
-                        }),
-                        primitive: wgpu::PrimitiveState {
-                            topology: wgpu::PrimitiveTopology::TriangleList,
-                            strip_index_format: None,
-                            front_face: wgpu::FrontFace::Ccw,
-                            cull_mode: Some(wgpu::Face::Back),
-                            unclipped_depth: false,
-                            polygon_mode: wgpu::PolygonMode::Fill,
-                            conservative: false,
-                        },
-                        depth_stencil: None,
-                        multisample: wgpu::MultisampleState {
-                            count: 1,
-                            mask: !0,
-                            alpha_to_coverage_enabled: false,
-                        },
-                        multiview: None,
-                    },
-                ));
-
-                render::Render {
-                    vertex_buffer,
-                    index_buffer,
-                    render_pipeline,
-                    index_count: vertex_indices.len() as _,
-                    transform_buffer: Some(std::rc::Rc::new(instance_buffer)),
-                    bind_groups: vec![
-                        (0, std::rc::Rc::new(texture_bind_group)),
-                        (1, camera_bind_group.clone()),
-                    ],
-                    instances: instances.len() as u32,
-                }
-            },
-        ];
+        let MapRenders {
+            tile_renderer,
+            map_layers,
+            collision_layer,
+            colliders,
+        } = MapRenders::new(
+            &map,
+            &device,
+            &queue,
+            &pipelines,
+            &camera_bind_group,
+            &surface_format,
+        )?;
+
+        // Sprites are packed in an atlas to be drawn together, other sprite
+        // images are to be added next to the character frames
+        let character_sprites = {
+            let sheet_image =
+                image::load_from_memory(include_bytes!("../resources/fullspritesheet.png"))
+                    .unwrap()
+                    .to_rgba8();
+            let sheet = pipeline::SpriteSheet::from_grid(
+                sheet_image.dimensions(),
+                CHARACTER_SPRITE_SIZE,
+                0,
+                0,
+            );
+            let mut atlas = atlas::AtlasBuilder::new(ATLAS_SIZE, 1, 1);
+            atlas.add_sheet("character", &sheet_image, &sheet);
+            let atlas = atlas.build(&device, &queue).unwrap();
+            let materials = atlas
+                .pages
+                .iter()
+                .map(|page| {
+                    pipelines.sprite_material(
+                        &device,
+                        page,
+                        pipeline::BlendMode::Replace,
+                        surface_format,
+                    )
+                })
+                .collect::<Vec<_>>();
+            (0..sheet.columns * sheet.rows)
+                .map(|index| {
+                    let sprite = atlas.sprite(&format!("character{index}")).unwrap();
+                    batch::Sprite::from_atlas(&materials, sprite)
+                })
+                .collect::<Vec<_>>()
+        };
+        let sprites = batch::SpriteBatch::new(&device, camera_bind_group.clone());
 
         // let transform_center =
         //     std::rc::Rc::new(std::cell::RefCell::new(transform::Transform::new()));
@@ -878,51 +720,11 @@ impl State {
             speed: Duration::from_millis(1000 / 15),
         };
 
-        let map_size = map.size.clone();
-
-        let mut map_data = vec![];
-        if let map::Layers::TileLayer { data, .. } = &map.layers[3] {
-            for i in (0..(map_size.0 * map_size.1) as usize)
-                .step_by(map_size.0 as usize)
-                .rev()
-            {
-                map_data.extend_from_slice(&data[i..(i as u32 + map_size.0) as usize])
-            }
-        }
-
-        let collision_transforms = (0..map_size.0 as i32)
-            .flat_map(|x: i32| {
-                let map_data = map_data.clone();
-                (0..map_size.1 as i32).map(move |y| {
-                    let mut t = transform::Transform::new();
-                    t.translate(&nalgebra_glm::vec3(
-                        x as f32 * map.tile_size.0 as f32 * 2.0,
-                        y as f32 * map.tile_size.1 as f32 * 2.0,
-                        0.0,
-                    ));
-                    t.label = Some(format!("{}", x + (y * map_size.1 as i32)).to_string());
-                    if map_data.len() > 0 {
-                        t.index = map_data[(x + (y * map_size.0 as i32)) as usize];
-                        t.flip_x = 1;
-                    }
-                    t
-                })
-            })
-            .collect::<Vec<_>>();
-
-        let mut cuboids_aabb = vec![];
-        for i in 0..collision_transforms.len() {
-            if collision_transforms[i].index == -1 {
-                continue;
-            }
-            let cuboid = parry2d::shape::Cuboid::new(nalgebra_glm::vec2(16.0, 16.0));
-            let pos = collision_transforms[i].position.xy();
-            let iso = nalgebra::Isometry2::translation(pos.x, pos.y);
-
-            cuboids_aabb.push(cuboid.aabb(&iso));
-        }
+        let editor = editor::Editor::new(&map, &world.current, &tile_renderer, &device);
+        let portals = world.portals(&map);
+        let fade = fade::Fade::new(&device, &pipelines, &surface_format);
 
-        Self {
+        Ok(Self {
             window,
             surface,
             device,
@@ -938,7 +740,7 @@ impl State {
             // uniforms_texture,
             time_since_last_frame: Duration::from_millis(1000 / 15),
             camera_buffer,
-            // camera_bind_group,
+            camera_bind_group,
             camera,
             camera_uniform,
             // transform_buffer,
@@ -951,16 +753,164 @@ impl State {
             zoom: 1.0,
             // indices: vertex_indices,
             mouse_pos: nalgebra_glm::vec3(0.0, 0.0, 0.0),
-            renders,
+            map,
+            tile_renderer,
+            map_layers,
+            collision_layer,
+            map_time: Duration::ZERO,
+            sprites,
+            character_sprites,
             instances,
-            collision: (collision_transforms, cuboids_aabb, false),
-        }
+            collision: (colliders, false),
+            editor,
+            modifiers: ModifiersState::empty(),
+            pipelines,
+            world,
+            portals,
+            in_portal: true,
+            transition: None,
+            fade,
+        })
     }
 
     pub fn window(&self) -> &Window {
         &self.window
     }
 
+    /// Changes cells (x, y) of the tile layer `layer` while the map runs, for
+    /// destructible terrain, doors or the editor. Only the GPU instances of the
+    /// cells are rewritten, and their colliders rebuilt.
+    fn set_tiles(&mut self, layer: usize, tiles: &[(i32, i32, map::Tile)]) {
+        let cells = tiles
+            .iter()
+            .filter(|&&(x, y, tile)| self.map.set_tile(layer, x, y, tile).is_some())
+            .map(|&(x, y, _)| (x, y))
+            .collect::<Vec<_>>();
+        if cells.is_empty() {
+            return;
+        }
+
+        let streamers = self
+            .map_layers
+            .iter_mut()
+            .filter_map(|map_layer| match map_layer {
+                MapLayer::Tiles(streamer) => Some(streamer),
+                MapLayer::Image(_) => None,
+            })
+            .chain(self.collision_layer.as_mut());
+        for streamer in streamers.filter(|streamer| streamer.layer == layer) {
+            streamer.set_tiles(
+                &cells,
+                &self.map,
+                &self.tile_renderer,
+                &self.device,
+                &self.queue,
+            );
+        }
+
+        let collision_layer = self.collision_layer.as_ref().map(|layer| layer.layer);
+        for (x, y) in cells {
+            self.collision.0.remove(&(layer, x, y));
+            if let Some(collider) = solid_cell(&self.map, collision_layer, layer, x, y) {
+                self.collision.0.insert((layer, x, y), collider);
+            }
+        }
+    }
+
+    /// Switches to the map `portal` leads to, building its renders and colliders,
+    /// and moves the player to the spawn of the portal.
+    fn switch_map(&mut self, portal: &map::Portal) {
+        if portal.map != self.world.current {
+            let previous = self.world.current.clone();
+            if !self.world.switch(&mut self.map, &portal.map) {
+                log::error!("Couldn't find the map {}", portal.map);
+                return;
+            }
+
+            let renders = match MapRenders::new(
+                &self.map,
+                &self.device,
+                &self.queue,
+                &self.pipelines,
+                &self.camera_bind_group,
+                &self.config.format,
+            ) {
+                Ok(renders) => renders,
+                Err(error) => {
+                    log::error!("Couldn't render the map {}: {}", portal.map, error);
+                    // The player stays on the map they were playing
+                    self.world.switch(&mut self.map, &previous);
+                    return;
+                }
+            };
+            self.tile_renderer = renders.tile_renderer;
+            self.map_layers = renders.map_layers;
+            self.collision_layer = renders.collision_layer;
+            self.collision = (renders.colliders, self.collision.1);
+            self.portals = self.world.portals(&self.map);
+
+            let enabled = self.editor.enabled;
+            self.editor = editor::Editor::new(
+                &self.map,
+                &self.world.current,
+                &self.tile_renderer,
+                &self.device,
+            );
+            self.editor.enabled = enabled;
+            self.show_editor_status();
+        }
+
+        let spawn = portal.spawn.as_deref().unwrap_or(SPAWN_POINT);
+        match self.map.find_object(spawn) {
+            Some(object) => {
+                let position = self.map.object_to_world(object.center());
+                self.transform
+                    .as_ref()
+                    .borrow_mut()
+                    .translate(&nalgebra_glm::vec3(position.x, position.y, 0.0));
+            }
+            None => log::warn!("Couldn't find the object {} in {}", spawn, portal.map),
+        }
+        // Spawning on a portal doesn't go through it
+        self.in_portal = true;
+    }
+
+    /// Goes through the portal the player steps in, and advances the fade between maps.
+    fn update_portals(&mut self, delta_time: Duration) {
+        let Some(mut transition) = self.transition.take() else {
+            let position = self.transform.as_ref().borrow().position.xy();
+            let player = parry2d::bounding_volume::Aabb::new(
+                (position - nalgebra_glm::vec2(8.0, 8.0)).into(),
+                (position + nalgebra_glm::vec2(8.0, 8.0)).into(),
+            );
+            let portal = self
+                .portals
+                .iter()
+                .find(|(_, area)| {
+                    parry2d::bounding_volume::BoundingVolume::intersects(area, &player)
+                })
+                .map(|(portal, _)| portal.clone());
+            if !self.in_portal {
+                self.transition = portal.clone().map(|portal| Transition {
+                    portal,
+                    time: Duration::ZERO,
+                    switched: false,
+                });
+            }
+            self.in_portal = portal.is_some();
+            return;
+        };
+
+        let (switch, alpha) = transition.update(delta_time);
+        if switch {
+            self.switch_map(&transition.portal);
+        }
+        self.fade.set(alpha.unwrap_or(0.0), &self.queue);
+        if alpha.is_some() {
+            self.transition = Some(transition);
+        }
+    }
+
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         if new_size.width > 0 && new_size.height > 0 {
             self.size = new_size;
@@ -970,8 +920,129 @@ impl State {
         }
     }
 
-    fn input(&mut self, _event: &WindowEvent) -> bool {
-        false
+    /// World position under the mouse cursor.
+    fn mouse_world_position(&self) -> nalgebra_glm::Vec2 {
+        let position = self.transform.as_ref().borrow().position.xy();
+        nalgebra_glm::vec2(
+            position.x + (self.mouse_pos.x - self.size.width as f32 * 0.5) * self.zoom,
+            position.y - (self.mouse_pos.y - self.size.height as f32 * 0.5) * self.zoom,
+        )
+    }
+
+    /// Applies `edits` of the editor to the map, in order.
+    fn apply_edits(&mut self, edits: &[editor::Edit]) {
+        let mut edits = edits.iter().peekable();
+        while let Some(edit) = edits.next() {
+            // Consecutive edits of a layer are set together
+            let mut tiles = vec![(edit.x, edit.y, edit.new)];
+            while let Some(next) = edits.next_if(|next| next.layer == edit.layer) {
+                tiles.push((next.x, next.y, next.new));
+            }
+            self.set_tiles(edit.layer, &tiles);
+        }
+    }
+
+    /// Applies `edits` made with the editor and keeps them for undo.
+    fn edit(&mut self, edits: Vec<editor::Edit>) {
+        self.apply_edits(&edits);
+        self.editor.record(edits);
+    }
+
+    fn show_editor_status(&self) {
+        match self.editor.enabled {
+            true => self.window.set_title(&self.editor.status(&self.map)),
+            false => self.window.set_title(TITLE),
+        }
+    }
+
+    /// Handles the mouse and the keys of the editor while it's enabled.
+    fn input(&mut self, event: &WindowEvent) -> bool {
+        if let WindowEvent::ModifiersChanged(modifiers) = event {
+            self.modifiers = *modifiers;
+        }
+        if !self.editor.enabled {
+            return false;
+        }
+
+        match event {
+            WindowEvent::MouseInput { state, button, .. } => {
+                let position = self.mouse_world_position();
+                let (x, y) = self.map.world_to_cell(position);
+                match (state, button) {
+                    (ElementState::Pressed, MouseButton::Left) => {
+                        match self.editor.palette_tile(&self.map, position) {
+                            Some(tile) => self.editor.select(tile),
+                            None => {
+                                let edits = self.editor.press(&self.map, x, y);
+                                self.edit(edits);
+                            }
+                        }
+                    }
+                    (ElementState::Released, MouseButton::Left) => {
+                        let edits = self.editor.release(&self.map, x, y);
+                        self.edit(edits);
+                    }
+                    (ElementState::Pressed, MouseButton::Right) => {
+                        self.editor
+                            .pick(&self.map, x, y, &self.tile_renderer, &self.device)
+                    }
+                    _ => return false,
+                }
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } => match (self.modifiers.ctrl(), key) {
+                (false, VirtualKeyCode::Key1) => self.editor.tool = editor::Tool::Paint,
+                (false, VirtualKeyCode::Key2) => self.editor.tool = editor::Tool::Erase,
+                (false, VirtualKeyCode::Key3) => self.editor.tool = editor::Tool::Box,
+                (false, VirtualKeyCode::Key4) => self.editor.tool = editor::Tool::Fill,
+                (false, VirtualKeyCode::L) => self.editor.next_layer(&self.map),
+                (false, VirtualKeyCode::LBracket | VirtualKeyCode::RBracket) => {
+                    let step = if *key == VirtualKeyCode::LBracket {
+                        -1
+                    } else {
+                        1
+                    };
+                    self.editor
+                        .next_tileset(&self.map, step, &self.tile_renderer, &self.device);
+                }
+                (false, VirtualKeyCode::PageUp | VirtualKeyCode::PageDown) => {
+                    let rows = if *key == VirtualKeyCode::PageUp {
+                        -1
+                    } else {
+                        1
+                    };
+                    self.editor
+                        .scroll(&self.map, rows, &self.tile_renderer, &self.device);
+                }
+                (true, VirtualKeyCode::Z) if self.modifiers.shift() => {
+                    let edits = self.editor.redo();
+                    self.apply_edits(&edits);
+                }
+                (true, VirtualKeyCode::Z) => {
+                    let edits = self.editor.undo();
+                    self.apply_edits(&edits);
+                }
+                (true, VirtualKeyCode::Y) => {
+                    let edits = self.editor.redo();
+                    self.apply_edits(&edits);
+                }
+                (true, VirtualKeyCode::S) => match self.editor.save(&self.map) {
+                    Ok(()) => log::info!("Saved the map"),
+                    Err(error) => log::error!("Couldn't save the map: {}", error),
+                },
+                _ => return false,
+            },
+            _ => return false,
+        }
+        self.show_editor_status();
+        true
     }
 
     fn update(&mut self, delta_time: Duration) {
@@ -1018,7 +1089,7 @@ impl State {
             //     // );
             // }
 
-            let mouse_pos = self.mouse_pos.clone();
+            let mouse_pos = self.mouse_pos;
             let screen_pos = nalgebra_glm::vec4(
                 (mouse_pos.x - self.size.width as f32 * 0.5) * self.zoom,
                 (mouse_pos.y - self.size.height as f32 * 0.5) * self.zoom,
@@ -1034,7 +1105,7 @@ impl State {
             let angle = diff.y.atan2(diff.x);
             transform.rotate(&nalgebra_glm::vec3(0.0, 0.0, angle.to_degrees()));
 
-            let transform_position = transform.position.clone();
+            let transform_position = transform.position;
             let running = if self.actions[6] { 4.0 } else { 1.0 };
 
             let new_position = transform_position + (direction * dt as f32 * 100.0 * running);
@@ -1042,11 +1113,10 @@ impl State {
             let player = parry2d::shape::Cuboid::new(nalgebra_glm::vec2(8.0, 8.0));
             let pos_p = new_position.clone().xy();
             let player_position = nalgebra::Isometry2::translation(pos_p.x, pos_p.y);
-            let player_aabb = player.aabb(&player_position);
 
             let mut collision = false;
-            for cuboid in self.collision.1.iter() {
-                if cuboid.intersects(&player_aabb) {
+            for collider in self.collision.0.values() {
+                if collider.intersects(&player, &player_position) {
                     collision = true;
                 }
             }
@@ -1078,6 +1148,8 @@ impl State {
             }
         }
 
+        self.update_portals(delta_time);
+
         // self.queue.write_buffer(
         //     &self.uniform_buffer,
         //     0,
@@ -1099,17 +1171,56 @@ impl State {
             bytemuck::cast_slice(&[self.camera_uniform]),
         );
 
-        self.renders[4].update_transform(
-            bytemuck::cast_slice(&[self.transform.as_ref().borrow().to_raw()]),
-            &mut self.queue,
-        );
+        self.map_time += delta_time;
+        let view = {
+            let position = self.transform.as_ref().borrow().position.xy();
+            let half_extents = nalgebra_glm::vec2(
+                self.size.width as f32 * self.zoom * 0.5,
+                self.size.height as f32 * self.zoom * 0.5,
+            );
+            parry2d::bounding_volume::Aabb::new(
+                (position - half_extents).into(),
+                (position + half_extents).into(),
+            )
+        };
+        if self.editor.enabled {
+            let edits = self.editor.hover(&self.map, self.mouse_world_position());
+            self.edit(edits);
+            self.editor.update(&view, &self.queue);
+        }
+        for layer in &mut self.map_layers {
+            match layer {
+                MapLayer::Tiles(layer) => {
+                    layer.update(
+                        &view,
+                        &self.map,
+                        &self.tile_renderer,
+                        &self.device,
+                        &self.queue,
+                    );
+                    layer.animate(&self.map, self.map_time, &self.queue);
+                }
+                MapLayer::Image(layer) => layer.update(&view, &self.map, &self.queue),
+            }
+        }
+        if let Some(layer) = &mut self.collision_layer {
+            layer.update(
+                &view,
+                &self.map,
+                &self.tile_renderer,
+                &self.device,
+                &self.queue,
+            );
+            layer.animate(&self.map, self.map_time, &self.queue);
+        }
 
-        let instance_data = self
-            .instances
-            .iter()
-            .map(|x| x.as_ref().borrow().to_raw())
-            .collect::<Vec<_>>();
-        self.renders[5].update_transform(bytemuck::cast_slice(&instance_data), &mut self.queue);
+        for transform in std::iter::once(&self.transform).chain(&self.instances) {
+            let transform = transform.as_ref().borrow();
+            if let Some(sprite) = self.character_sprites.get(transform.index as usize) {
+                self.sprites.draw(sprite, &transform);
+            }
+        }
+        self.sprites.prepare(&self.device, &self.queue);
     }
 
     fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
@@ -1143,14 +1254,19 @@ impl State {
                 depth_stencil_attachment: None,
             });
 
-            self.renders[0].draw(&mut _render_pass);
-            self.renders[1].draw(&mut _render_pass);
-            self.renders[2].draw(&mut _render_pass);
-            if self.collision.2 {
-                self.renders[3].draw(&mut _render_pass);
+            self.map_layers.iter().for_each(|layer| match layer {
+                MapLayer::Tiles(layer) => layer.draw(&mut _render_pass),
+                MapLayer::Image(layer) => layer.draw(&mut _render_pass),
+            });
+            match &self.collision_layer {
+                Some(collision_layer) if self.collision.1 => {
+                    collision_layer.draw(&mut _render_pass)
+                }
+                _ => {}
             }
-            self.renders[4].draw(&mut _render_pass);
-            self.renders[5].draw(&mut _render_pass);
+            self.sprites.render(&mut _render_pass);
+            self.editor.draw(&mut _render_pass);
+            self.fade.draw(&mut _render_pass);
         }
 
         self.queue.submit(std::iter::once(encoder.finish()));
@@ -1160,3 +1276,49 @@ impl State {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    fn transition(fade: std::time::Duration) -> super::Transition {
+        super::Transition {
+            portal: super::map::Portal {
+                map: "./resources/mapa.json".to_string(),
+                spawn: None,
+                fade,
+            },
+            time: std::time::Duration::ZERO,
+            switched: false,
+        }
+    }
+
+    #[test]
+    fn ldtk_int_grid_values_block() {
+        let map = pollster::block_on(super::map::load_map("./resources/ldtk/world.ldtk")).unwrap();
+        let walls = super::collision_layer(&map);
+        assert_eq!(walls, map.find_layer("Walls"));
+
+        let blocks = |x, y| super::solid_cell(&map, walls, walls.unwrap(), x, y).is_some();
+        assert!(blocks(0, 0));
+        assert!(blocks(5, 1));
+        assert!(!blocks(1, 0));
+    }
+
+    #[test]
+    fn zero_fade_switches_and_ends_at_once() {
+        let mut transition = transition(std::time::Duration::ZERO);
+        assert_eq!(
+            transition.update(std::time::Duration::from_millis(16)),
+            (true, None)
+        );
+    }
+
+    #[test]
+    fn fade_switches_once_when_black() {
+        let mut transition = transition(std::time::Duration::from_millis(100));
+        let step = std::time::Duration::from_millis(50);
+        assert_eq!(transition.update(step), (false, Some(0.5)));
+        assert_eq!(transition.update(step), (true, Some(1.0)));
+        assert_eq!(transition.update(step), (false, Some(0.5)));
+        assert_eq!(transition.update(step), (false, None));
+    }
+}
@@ -1,4 +1,20 @@
-use wgpu::util::DeviceExt;
+mod error;
+mod generator;
+mod json;
+mod ldtk;
+mod orientation;
+mod tmx;
+mod wang;
+mod world;
+mod writer;
+
+pub use error::MapError;
+pub use generator::Generator;
+pub use orientation::{Orientation, RenderOrder};
+pub use world::{Portal, World};
+pub use writer::save_map;
+
+use wang::{WangColor, WangSet, WangSetType};
 
 #[cfg(target_arch = "wasm32")]
 use web_sys::{Request, RequestInit, RequestMode, Response};
@@ -12,391 +28,1090 @@ use wasm_bindgen_futures::*;
 #[cfg(target_arch = "wasm32")]
 use web_sys::Blob;
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct TileSet {
+    pub name: String,
+    pub first_gid: u32,
     pub image: Vec<u8>,
+    /// Where `image` was loaded from, relative to the working directory.
+    pub image_path: String,
     pub columns: u32,
     pub tile_count: u32,
+    pub tile_size: (u32, u32),
+    /// Pixels around the tiles of `image`.
+    pub margin: u32,
+    /// Pixels between the tiles of `image`.
+    pub spacing: u32,
     pub image_size: (u32, u32),
+    pub properties: Properties,
+    /// Keyed by local tile id, only tiles with data are present.
+    pub tiles: std::collections::BTreeMap<u32, TileData>,
+    /// Terrains to autotile with, see `TileSet::autotile`.
+    pub wang_sets: Vec<WangSet>,
+}
+
+impl TileSet {
+    pub fn rows(&self) -> u32 {
+        self.tile_count.div_ceil(self.columns.max(1))
+    }
+
+    /// How the tiles are laid out in `image`.
+    pub fn sprite_sheet(&self) -> super::pipeline::SpriteSheet {
+        super::pipeline::SpriteSheet {
+            columns: self.columns,
+            rows: self.rows(),
+            tile_size: self.tile_size,
+            margin: self.margin,
+            spacing: self.spacing,
+        }
+    }
+}
+
+const FLIPPED_HORIZONTALLY_FLAG: u32 = 0x8000_0000;
+const FLIPPED_VERTICALLY_FLAG: u32 = 0x4000_0000;
+const FLIPPED_DIAGONALLY_FLAG: u32 = 0x2000_0000;
+const ROTATED_HEXAGONAL_120_FLAG: u32 = 0x1000_0000;
+
+/// A tile layer cell, with the flip flags Tiled stores in the top bits of the GID.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Tile {
+    pub gid: u32,
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+    pub flip_diagonal: bool,
+    pub rotate_hexagonal_120: bool,
 }
 
-#[derive(Debug)]
+impl Tile {
+    pub fn from_raw(raw: u32) -> Self {
+        Self {
+            gid: raw
+                & !(FLIPPED_HORIZONTALLY_FLAG
+                    | FLIPPED_VERTICALLY_FLAG
+                    | FLIPPED_DIAGONALLY_FLAG
+                    | ROTATED_HEXAGONAL_120_FLAG),
+            flip_horizontal: raw & FLIPPED_HORIZONTALLY_FLAG != 0,
+            flip_vertical: raw & FLIPPED_VERTICALLY_FLAG != 0,
+            flip_diagonal: raw & FLIPPED_DIAGONALLY_FLAG != 0,
+            rotate_hexagonal_120: raw & ROTATED_HEXAGONAL_120_FLAG != 0,
+        }
+    }
+
+    pub fn to_raw(self) -> u32 {
+        let mut raw = self.gid;
+        if self.flip_horizontal {
+            raw |= FLIPPED_HORIZONTALLY_FLAG;
+        }
+        if self.flip_vertical {
+            raw |= FLIPPED_VERTICALLY_FLAG;
+        }
+        if self.flip_diagonal {
+            raw |= FLIPPED_DIAGONALLY_FLAG;
+        }
+        if self.rotate_hexagonal_120 {
+            raw |= ROTATED_HEXAGONAL_120_FLAG;
+        }
+        raw
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.gid == 0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shape {
+    Rectangle,
+    Point,
+    Ellipse,
+    Polygon(Vec<(f32, f32)>),
+    Polyline(Vec<(f32, f32)>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Object {
     pub name: String,
     pub id: u32,
-    pub position: (u32, u32),
-    pub size: (u32, u32),
+    pub class: String,
+    pub position: (f32, f32),
+    pub size: (f32, f32),
+    pub rotation: f32,
+    pub visible: bool,
+    pub shape: Shape,
+    pub properties: Properties,
 }
 
-#[derive(Debug)]
-pub struct TileLayer {
-    pub data: Vec<u32>,
-    pub id: u32,
-    pub name: String,
+impl Object {
+    /// Center of the object in Tiled pixel coordinates (y down).
+    /// Points and polygons use their origin, as Tiled does.
+    pub fn center(&self) -> (f32, f32) {
+        match self.shape {
+            Shape::Rectangle | Shape::Ellipse => (
+                self.position.0 + self.size.0 * 0.5,
+                self.position.1 + self.size.1 * 0.5,
+            ),
+            _ => self.position,
+        }
+    }
+}
+
+/// A block of tiles of an infinite map layer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    /// Top-left cell of the chunk, in tiles.
+    pub position: (i32, i32),
     pub size: (u32, u32),
+    pub data: Vec<Tile>,
+}
+
+impl Chunk {
+    pub fn tile(&self, x: i32, y: i32) -> Option<Tile> {
+        let (x, y) = (x - self.position.0, y - self.position.1);
+        if x < 0 || y < 0 || x >= self.size.0 as i32 || y >= self.size.1 as i32 {
+            return None;
+        }
+        Some(self.data[(x + y * self.size.0 as i32) as usize])
+    }
+}
+
+/// How a layer is drawn, as set in the layer properties panel of Tiled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerAttributes {
+    pub opacity: f32,
+    /// RGBA, white when the layer has no tint.
+    pub tint_color: [f32; 4],
+    /// In Tiled pixels (y down).
+    pub offset: (f32, f32),
+    pub parallax: (f32, f32),
+}
+
+impl Default for LayerAttributes {
+    fn default() -> Self {
+        Self {
+            opacity: 1.0,
+            tint_color: [1.0; 4],
+            offset: (0.0, 0.0),
+            parallax: (1.0, 1.0),
+        }
+    }
+}
+
+/// Parses a Tiled color, `#RRGGBB` or `#AARRGGBB`, into RGBA.
+fn parse_color(color: &str) -> Option<[f32; 4]> {
+    let color = color.strip_prefix('#').unwrap_or(color);
+    let value = u32::from_str_radix(color, 16).ok()?;
+    let channel = |shift: u32| ((value >> shift) & 0xff) as f32 / 255.0;
+    match color.len() {
+        6 => Some([channel(16), channel(8), channel(0), 1.0]),
+        8 => Some([channel(16), channel(8), channel(0), channel(24)]),
+        _ => None,
+    }
+}
+
+/// Value of a custom property, typed as in Tiled.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropertyValue {
+    String(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    /// RGBA, `None` when the color is unset.
+    Color(Option<[f32; 4]>),
+    /// Path relative to the file the property was read from.
+    File(String),
+    /// ID of the referenced object, 0 when unset.
+    Object(u32),
+    /// Instance of a custom class, with the members set on it.
+    Class {
+        class: String,
+        properties: Properties,
+    },
+}
+
+impl PropertyValue {
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            PropertyValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Ints are converted as well.
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            PropertyValue::Float(value) => Some(*value),
+            PropertyValue::Int(value) => Some(*value as f64),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            PropertyValue::String(value) | PropertyValue::File(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// Custom properties by name.
+pub type Properties = std::collections::BTreeMap<String, PropertyValue>;
+
+/// A frame of an animated tile.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame {
+    /// Local id, in the same tileset, of the tile shown.
+    pub tile_id: u32,
+    pub duration: std::time::Duration,
+}
+
+/// Data attached to a single tile of a tileset.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TileData {
+    pub properties: Properties,
+    /// Empty when the tile isn't animated.
+    pub animation: Vec<Frame>,
+    /// Collision shapes, relative to the top-left of the tile.
+    pub objects: Vec<Object>,
+}
+
+impl TileData {
+    /// Local id of the tile shown `time` after the animation started, looping.
+    pub fn frame(&self, time: std::time::Duration) -> Option<u32> {
+        let total = self
+            .animation
+            .iter()
+            .map(|frame| frame.duration.as_millis())
+            .sum::<u128>();
+        if total == 0 {
+            return self.animation.first().map(|frame| frame.tile_id);
+        }
+
+        let mut time = time.as_millis() % total;
+        self.animation.iter().find_map(|frame| {
+            if time < frame.duration.as_millis() {
+                return Some(frame.tile_id);
+            }
+            time -= frame.duration.as_millis();
+            None
+        })
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum Layers {
     ObjectGroup {
         id: u32,
         name: String,
         visible: bool,
+        attributes: LayerAttributes,
+        properties: Properties,
         objects: Vec<Object>,
     },
     TileLayer {
         id: u32,
         name: String,
         visible: bool,
-        data: Vec<i32>,
+        attributes: LayerAttributes,
+        properties: Properties,
+        /// Tiles of a finite map, empty on infinite maps.
+        data: Vec<Tile>,
+        /// Tiles of an infinite map, empty on finite maps.
+        chunks: Vec<Chunk>,
+    },
+    /// A single image, drawn from the top-left of the map.
+    ImageLayer {
+        id: u32,
+        name: String,
+        visible: bool,
+        attributes: LayerAttributes,
+        properties: Properties,
+        /// Empty when the layer has no image.
+        image: Vec<u8>,
+        /// Where `image` was loaded from, relative to the working directory.
+        image_path: String,
+        image_size: (u32, u32),
+        /// Whether the image is tiled along x and y.
+        repeat: (bool, bool),
     },
+    /// Its visibility, opacity, tint, offset and parallax apply to the layers in it.
+    Group {
+        id: u32,
+        name: String,
+        visible: bool,
+        attributes: LayerAttributes,
+        properties: Properties,
+        layers: Vec<Layers>,
+    },
+}
+
+impl Layers {
+    pub fn name(&self) -> &str {
+        match self {
+            Layers::ObjectGroup { name, .. }
+            | Layers::TileLayer { name, .. }
+            | Layers::ImageLayer { name, .. }
+            | Layers::Group { name, .. } => name,
+        }
+    }
+
+    /// Whether the layer itself is visible, see `Map::is_layer_visible` for
+    /// the visibility inherited from its groups.
+    pub fn visible(&self) -> bool {
+        match self {
+            Layers::ObjectGroup { visible, .. }
+            | Layers::TileLayer { visible, .. }
+            | Layers::ImageLayer { visible, .. }
+            | Layers::Group { visible, .. } => *visible,
+        }
+    }
+
+    pub fn attributes(&self) -> &LayerAttributes {
+        match self {
+            Layers::ObjectGroup { attributes, .. }
+            | Layers::TileLayer { attributes, .. }
+            | Layers::ImageLayer { attributes, .. }
+            | Layers::Group { attributes, .. } => attributes,
+        }
+    }
+
+    pub fn properties(&self) -> &Properties {
+        match self {
+            Layers::ObjectGroup { properties, .. }
+            | Layers::TileLayer { properties, .. }
+            | Layers::ImageLayer { properties, .. }
+            | Layers::Group { properties, .. } => properties,
+        }
+    }
+
+    /// Layers of a group, empty for any other layer.
+    pub fn children(&self) -> &[Layers] {
+        match self {
+            Layers::Group { layers, .. } => layers,
+            _ => &[],
+        }
+    }
+
+    /// Number of layers nested in this one, at any depth.
+    fn descendants(&self) -> usize {
+        self.children()
+            .iter()
+            .map(|layer| 1 + layer.descendants())
+            .sum()
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct Map {
+    /// Size in tiles, only a hint of the bounds on infinite maps.
     pub size: (u32, u32),
+    pub infinite: bool,
+    pub orientation: Orientation,
+    pub render_order: RenderOrder,
     pub tile_size: (u32, u32),
+    /// Tiled pixel position where parallax layers line up with the others.
+    pub parallax_origin: (f32, f32),
+    pub properties: Properties,
+    /// Top level layers, groups hold the layers nested in them.
     pub layers: Vec<Layers>,
-    pub tileset: TileSet,
-}
-
-pub async fn load_map(path_data: &str) -> Map {
-    let json_file = {
-        cfg_if::cfg_if! {
-            if #[cfg(target_arch = "wasm32")] {
-                let mut opts = RequestInit::new();
-                opts.method("GET");
-                opts.mode(RequestMode::Cors);
-
-                let url = format!("{}", path_data);
-                let request = Request::new_with_str_and_init(&url, &opts).unwrap();
-                let window = web_sys::window().unwrap();
-                let resp_value = JsFuture::from(window.fetch_with_request(&request))
-                    .await
-                    .unwrap();
-                let resp: Response = resp_value.dyn_into().unwrap();
-                let json = JsFuture::from(resp.json().unwrap()).await.unwrap();
-                serde_wasm_bindgen::from_value::<serde_json::Value>(json).unwrap()
-            } else {
-                let data  = std::fs::read_to_string(path_data).unwrap();
-                serde_json::from_str::<serde_json::Value>(data.as_str()).unwrap()
+    /// Sorted by `first_gid`.
+    pub tilesets: Vec<TileSet>,
+}
+
+impl Map {
+    /// Every layer in draw order, each group followed by the layers in it.
+    /// Layer ids used by `Map` are indices in this order.
+    pub fn all_layers(&self) -> Vec<&Layers> {
+        fn push<'a>(layers: &'a [Layers], all: &mut Vec<&'a Layers>) {
+            for layer in layers {
+                all.push(layer);
+                push(layer.children(), all);
             }
         }
-    };
 
-    let filename = {
-        let mut f = "./resources/".to_owned();
-        f.push_str(json_file["tilesets"][0]["image"].as_str().unwrap());
-        f
-    };
+        let mut all = vec![];
+        push(&self.layers, &mut all);
+        all
+    }
 
-    let spritesheet = {
-        cfg_if::cfg_if! {
-            if #[cfg(target_arch = "wasm32")] {
-                let mut opts = RequestInit::new();
-                opts.method("GET");
-                opts.mode(RequestMode::Cors);
-
-                let url = format!("{}", filename.as_str());
-                let request = Request::new_with_str_and_init(&url, &opts).unwrap();
-                let window = web_sys::window().unwrap();
-                let resp_value = JsFuture::from(window.fetch_with_request(&request))
-                    .await
-                    .unwrap();
-                let resp: Response = resp_value.dyn_into().unwrap();
-                let blob_response = JsFuture::from(resp.blob().unwrap()).await.unwrap();
-
-                let blob: Blob = blob_response.into();
-
-                let array_buffer_promise: JsFuture = blob.array_buffer().into();
-                let array_buffer: JsValue = array_buffer_promise.await.unwrap();
-                js_sys::Uint8Array::new(&array_buffer).to_vec()
-            } else {
-                std::fs::read(filename).unwrap()
+    /// Walks down to the layer `id`, calling `f` on each group it is nested in
+    /// (outermost first) and then on the layer.
+    fn visit_layer<'a>(&'a self, id: usize, mut f: impl FnMut(&'a Layers)) -> &'a Layers {
+        let (mut layers, mut id) = (self.layers.as_slice(), id);
+        loop {
+            let mut children = None;
+            for layer in layers {
+                if id == 0 {
+                    f(layer);
+                    return layer;
+                }
+                id -= 1;
+                if id < layer.descendants() {
+                    f(layer);
+                    children = Some(layer.children());
+                    break;
+                }
+                id -= layer.descendants();
             }
+            layers = children.expect("layer id out of range");
+        }
+    }
+
+    pub fn layer(&self, id: usize) -> &Layers {
+        self.visit_layer(id, |_| {})
+    }
+
+    fn layer_mut(&mut self, id: usize) -> &mut Layers {
+        fn find(layers: &mut [Layers], mut id: usize) -> &mut Layers {
+            for layer in layers {
+                if id == 0 {
+                    return layer;
+                }
+                id -= 1;
+                if id < layer.descendants() {
+                    let Layers::Group { layers, .. } = layer else {
+                        unreachable!("only groups have layers nested in them");
+                    };
+                    return find(layers, id);
+                }
+                id -= layer.descendants();
+            }
+            panic!("layer id out of range");
+        }
+        find(&mut self.layers, id)
+    }
+
+    /// Whether the layer `id` and every group it is in are visible.
+    pub fn is_layer_visible(&self, id: usize) -> bool {
+        let mut visible = true;
+        self.visit_layer(id, |layer| visible &= layer.visible());
+        visible
+    }
+
+    pub fn objects(&self) -> impl Iterator<Item = &Object> {
+        self.all_layers().into_iter().flat_map(|layer| match layer {
+            Layers::ObjectGroup { objects, .. } => objects.iter(),
+            _ => [].iter(),
+        })
+    }
+
+    pub fn find_object(&self, name: &str) -> Option<&Object> {
+        self.objects().find(|object| object.name == name)
+    }
+
+    /// Id of the first layer called `name`.
+    pub fn find_layer(&self, name: &str) -> Option<usize> {
+        self.all_layers()
+            .iter()
+            .position(|layer| layer.name() == name)
+    }
+
+    /// Ids of the tile layers, in draw order.
+    pub fn tile_layers(&self) -> impl Iterator<Item = usize> + '_ {
+        self.all_layers()
+            .into_iter()
+            .enumerate()
+            .filter(|(_, layer)| matches!(layer, Layers::TileLayer { .. }))
+            .map(|(id, _)| id)
+    }
+
+    /// Attributes of the layer `id` combined with the ones of the groups it is in,
+    /// Tiled multiplies opacities, tints and parallax factors and adds offsets.
+    pub fn layer_attributes(&self, id: usize) -> LayerAttributes {
+        let mut combined = LayerAttributes::default();
+        self.visit_layer(id, |layer| {
+            let attributes = layer.attributes();
+            combined.opacity *= attributes.opacity;
+            for (channel, tint) in combined.tint_color.iter_mut().zip(attributes.tint_color) {
+                *channel *= tint;
+            }
+            combined.offset.0 += attributes.offset.0;
+            combined.offset.1 += attributes.offset.1;
+            combined.parallax.0 *= attributes.parallax.0;
+            combined.parallax.1 *= attributes.parallax.1;
+        });
+        combined
+    }
+
+    /// World translation of the layer `id` when the view is centered at `camera`,
+    /// its offset plus the parallax scrolling relative to the parallax origin.
+    pub fn layer_shift(&self, id: usize, camera: nalgebra_glm::Vec2) -> nalgebra_glm::Vec2 {
+        let attributes = self.layer_attributes(id);
+        let origin = self.to_world(self.parallax_origin);
+        nalgebra_glm::vec2(
+            attributes.offset.0 * 2.0 + (camera.x - origin.x) * (1.0 - attributes.parallax.0),
+            -attributes.offset.1 * 2.0 + (camera.y - origin.y) * (1.0 - attributes.parallax.1),
+        )
+    }
+
+    /// Resolves a GID into (tileset index, local tile id) through `firstgid`.
+    pub fn resolve_gid(&self, gid: u32) -> Option<(usize, u32)> {
+        if gid == 0 {
+            return None;
+        }
+
+        self.tilesets
+            .iter()
+            .rposition(|tileset| tileset.first_gid <= gid)
+            .map(|index| (index, gid - self.tilesets[index].first_gid))
+    }
+
+    /// Properties set on the tile a GID resolves to, if any.
+    pub fn tile_properties(&self, gid: u32) -> Option<&Properties> {
+        let (tileset, local_id) = self.resolve_gid(gid)?;
+        self.tilesets[tileset]
+            .tiles
+            .get(&local_id)
+            .map(|tile| &tile.properties)
+    }
+
+    /// Tile at cell (x, y) of a tile layer, empty outside of it.
+    pub fn tile(&self, id: usize, x: i32, y: i32) -> Tile {
+        match self.layer(id) {
+            Layers::TileLayer { chunks, .. } if self.infinite => chunks
+                .iter()
+                .find_map(|chunk| chunk.tile(x, y))
+                .unwrap_or_default(),
+            Layers::TileLayer { data, .. } => {
+                if x < 0 || y < 0 || x >= self.size.0 as i32 || y >= self.size.1 as i32 {
+                    return Tile::default();
+                }
+                data[(x + y * self.size.0 as i32) as usize]
+            }
+            _ => Tile::default(),
+        }
+    }
+
+    /// (position, size) in tiles of the chunks of a tile layer. Infinite maps
+    /// use the chunks stored by Tiled, finite maps are split in `chunk_size` squares.
+    pub fn chunk_bounds(&self, id: usize, chunk_size: u32) -> Vec<((i32, i32), (u32, u32))> {
+        match self.layer(id) {
+            Layers::TileLayer { chunks, .. } if self.infinite => chunks
+                .iter()
+                .map(|chunk| (chunk.position, chunk.size))
+                .collect(),
+            Layers::TileLayer { .. } => (0..self.size.1)
+                .step_by(chunk_size as usize)
+                .flat_map(|y| {
+                    (0..self.size.0).step_by(chunk_size as usize).map(move |x| {
+                        (
+                            (x as i32, y as i32),
+                            (
+                                chunk_size.min(self.size.0 - x),
+                                chunk_size.min(self.size.1 - y),
+                            ),
+                        )
+                    })
+                })
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Sets the cell (x, y) of the tile layer `id`, returning the tile it had.
+    /// Infinite maps get a new chunk for cells outside theirs, on finite maps
+    /// cells outside the map are left alone and `None` is returned.
+    pub fn set_tile(&mut self, id: usize, x: i32, y: i32, tile: Tile) -> Option<Tile> {
+        let (size, infinite) = (self.size, self.infinite);
+        let Layers::TileLayer { data, chunks, .. } = self.layer_mut(id) else {
+            return None;
+        };
+
+        if infinite {
+            let chunk = match chunks.iter().position(|chunk| chunk.tile(x, y).is_some()) {
+                Some(chunk) => &mut chunks[chunk],
+                None => {
+                    // Aligned to the chunk size, as Tiled does
+                    let chunk_size = chunks.first().map_or((16, 16), |chunk| chunk.size);
+                    chunks.push(Chunk {
+                        position: (
+                            x.div_euclid(chunk_size.0 as i32) * chunk_size.0 as i32,
+                            y.div_euclid(chunk_size.1 as i32) * chunk_size.1 as i32,
+                        ),
+                        size: chunk_size,
+                        data: vec![Tile::default(); (chunk_size.0 * chunk_size.1) as usize],
+                    });
+                    chunks.last_mut().unwrap()
+                }
+            };
+            let cell = x - chunk.position.0 + (y - chunk.position.1) * chunk.size.0 as i32;
+            return Some(std::mem::replace(&mut chunk.data[cell as usize], tile));
+        }
+
+        if x < 0 || y < 0 || x >= size.0 as i32 || y >= size.1 as i32 {
+            return None;
+        }
+        if data.is_empty() {
+            *data = vec![Tile::default(); (size.0 * size.1) as usize];
+        }
+        Some(std::mem::replace(
+            &mut data[(x + y * size.0 as i32) as usize],
+            tile,
+        ))
+    }
+}
+
+/// Loads a Tiled map, `.tmx` files are read as XML and anything else as JSON.
+/// LDtk projects (`.ldtk`) are loaded as a map too, see `ldtk::load_map`.
+pub async fn load_map(path_data: &str) -> Result<Map, MapError> {
+    match std::path::Path::new(path_data)
+        .extension()
+        .and_then(|extension| extension.to_str())
+    {
+        Some("tmx") => tmx::load_map(path_data).await,
+        Some("ldtk") => ldtk::load_map(path_data).await,
+        _ => json::load_map(path_data).await,
+    }
+}
+
+/// Decodes base64 tile data, optionally zlib, gzip or zstd compressed,
+/// into tiles. GIDs are stored as little-endian u32.
+fn decode_tile_data(data: &str, compression: &str) -> Result<Vec<Tile>, MapError> {
+    use base64::Engine;
+    use std::io::Read;
+
+    let data = data
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>();
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .map_err(|error| MapError::InvalidTileData(error.to_string()))?;
+
+    let mut decompressed = vec![];
+    let result = match compression {
+        "" => return Ok(tiles_from_bytes(&bytes)),
+        "zlib" => flate2::read::ZlibDecoder::new(bytes.as_slice()).read_to_end(&mut decompressed),
+        "gzip" => flate2::read::GzDecoder::new(bytes.as_slice()).read_to_end(&mut decompressed),
+        "zstd" => ruzstd::StreamingDecoder::new(bytes.as_slice())
+            .map_err(|error| MapError::InvalidTileData(error.to_string()))?
+            .read_to_end(&mut decompressed),
+        compression => {
+            return Err(MapError::UnsupportedEncoding(format!(
+                "{} compression",
+                compression
+            )))
         }
     };
+    result.map_err(|error| MapError::InvalidTileData(error.to_string()))?;
+
+    Ok(tiles_from_bytes(&decompressed))
+}
+
+fn tiles_from_bytes(bytes: &[u8]) -> Vec<Tile> {
+    bytes
+        .chunks_exact(4)
+        .map(|gid| Tile::from_raw(u32::from_le_bytes([gid[0], gid[1], gid[2], gid[3]])))
+        .collect()
+}
+
+/// Resolves `relative` against the directory of the file at `base`.
+fn resolve_path(base: &str, relative: &str) -> String {
+    normalize_path(
+        &std::path::Path::new(base)
+            .parent()
+            .map(|parent| parent.join(relative))
+            .unwrap_or_else(|| relative.into())
+            .to_string_lossy(),
+    )
+}
 
-    let map = Map {
-        size: (
-            json_file["width"].as_u64().unwrap() as u32,
-            json_file["height"].as_u64().unwrap() as u32,
-        ),
-        tile_size: (
-            json_file["tilewidth"].as_u64().unwrap() as u32,
-            json_file["tileheight"].as_u64().unwrap() as u32,
-        ),
-        layers: {
-            let mut layers: Vec<Layers> = vec![];
-            for value in json_file["layers"].as_array().unwrap() {
-                match value["type"].as_str().unwrap() {
-                    "tilelayer" => {
-                        layers.push(Layers::TileLayer {
-                            id: value["id"].as_u64().unwrap() as u32,
-                            name: value["name"].as_str().unwrap().to_string(),
-                            visible: value["visible"].as_bool().unwrap(),
-                            data: value["data"]
-                                .as_array()
-                                .unwrap()
-                                .iter()
-                                .map(|x| (x.as_i64().unwrap() as i32) - 1)
-                                .collect::<Vec<i32>>(),
+/// `path` with its `.` and `..` components collapsed, so every path to a file is
+/// spelled the same. Done on the text, as the web has no file system to ask.
+fn normalize_path(path: &str) -> String {
+    use std::path::Component;
+
+    let mut components: Vec<Component> = Vec::new();
+    for component in std::path::Path::new(path).components() {
+        match component {
+            Component::CurDir if !components.is_empty() => {}
+            Component::ParentDir if matches!(components.last(), Some(Component::Normal(_))) => {
+                components.pop();
+            }
+            component => components.push(component),
+        }
+    }
+    components
+        .iter()
+        .collect::<std::path::PathBuf>()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Loads a tileset or image layer image along with the size in its header. The
+/// pixels are decoded when the map is rendered, see `TileRenderer::new`.
+async fn load_image(path: &str) -> Result<(Vec<u8>, (u32, u32)), MapError> {
+    let bytes = load_bytes(path).await?;
+    let size = image::io::Reader::new(std::io::Cursor::new(&bytes))
+        .with_guessed_format()
+        .map_err(|error| error.to_string())
+        .and_then(|reader| reader.into_dimensions().map_err(|error| error.to_string()))
+        .map_err(|message| MapError::Image {
+            path: path.to_string(),
+            message,
+        })?;
+    Ok((bytes, size))
+}
+
+async fn load_bytes(path: &str) -> Result<Vec<u8>, MapError> {
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "wasm32")] {
+            let io_error = |error: JsValue| MapError::Io {
+                path: path.to_string(),
+                message: format!("{:?}", error),
+            };
+
+            let mut opts = RequestInit::new();
+            opts.method("GET");
+            opts.mode(RequestMode::Cors);
+
+            let request = Request::new_with_str_and_init(path, &opts).map_err(io_error)?;
+            let window = web_sys::window().unwrap();
+            let resp_value = JsFuture::from(window.fetch_with_request(&request))
+                .await
+                .map_err(io_error)?;
+            let resp: Response = resp_value.dyn_into().unwrap();
+            if !resp.ok() {
+                return Err(MapError::Io {
+                    path: path.to_string(),
+                    message: format!("HTTP {} {}", resp.status(), resp.status_text()),
+                });
+            }
+            let blob_response = JsFuture::from(resp.blob().map_err(io_error)?)
+                .await
+                .map_err(io_error)?;
+
+            let blob: Blob = blob_response.into();
+
+            let array_buffer_promise: JsFuture = blob.array_buffer().into();
+            let array_buffer: JsValue = array_buffer_promise.await.map_err(io_error)?;
+            Ok(js_sys::Uint8Array::new(&array_buffer).to_vec())
+        } else {
+            std::fs::read(path).map_err(|error| MapError::Io {
+                path: path.to_string(),
+                message: error.to_string(),
+            })
+        }
+    }
+}
+
+/// Tint and world translation of a tile layer, updated as the camera moves.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LayerUniform {
+    tint_color: [f32; 4],
+    shift: [f32; 2],
+    _padding: [f32; 2],
+}
+
+impl LayerUniform {
+    pub fn new(map: &Map, id: usize, camera: nalgebra_glm::Vec2) -> Self {
+        let attributes = map.layer_attributes(id);
+        let [r, g, b, a] = attributes.tint_color;
+        let shift = map.layer_shift(id, camera);
+        Self {
+            tint_color: [r, g, b, a * attributes.opacity],
+            shift: [shift.x, shift.y],
+            _padding: [0.0; 2],
+        }
+    }
+
+    /// Uniform of an untinted overlay moved by `shift`, like the editor palette.
+    pub fn overlay(shift: nalgebra_glm::Vec2) -> Self {
+        Self {
+            tint_color: [1.0; 4],
+            shift: [shift.x, shift.y],
+            _padding: [0.0; 2],
+        }
+    }
+}
+
+/// An instance of an animated tile in a `TileRender`, and the frame it shows.
+struct AnimatedInstance {
+    instance: u32,
+    tile_id: u32,
+    frame: u32,
+}
+
+/// Render of the cells of a chunk, or of a draw row of one, that use the same tileset.
+pub struct TileRender {
+    pub render: super::render::Render,
+    tileset: usize,
+    animated: Vec<AnimatedInstance>,
+    /// Cell of each instance.
+    cells: Vec<(i32, i32)>,
+}
+
+impl TileRender {
+    /// Rewrites the instances of cell (x, y) of the layer `id` in `renders`, the renders
+    /// of its chunk, after it changed in `map`. Returns false when the cell has no instance
+    /// in the render of its new tileset, the chunk has to be generated again then.
+    pub fn set_tile(
+        renders: &mut [TileRender],
+        id: usize,
+        map: &Map,
+        x: i32,
+        y: i32,
+        queue: &wgpu::Queue,
+    ) -> bool {
+        let tile = tile_instance(id, map, x, y);
+        let mut placed = tile.is_none();
+        for render in renders {
+            let Some(instance) = render.cells.iter().position(|&cell| cell == (x, y)) else {
+                continue;
+            };
+            render
+                .animated
+                .retain(|animated| animated.instance != instance as u32);
+
+            // Cells without a tile of the render's tileset are left as an empty quad
+            let raw = match tile {
+                Some((tileset, local_id, raw, is_animated)) if tileset == render.tileset => {
+                    if is_animated {
+                        render.animated.push(AnimatedInstance {
+                            instance: instance as u32,
+                            tile_id: local_id,
+                            frame: local_id,
                         });
                     }
-                    "objectgroup" => {
-                        // println!("OBJECT GROUP")
-                    }
-                    _ => {}
+                    placed = true;
+                    raw
                 }
+                _ => bytemuck::Zeroable::zeroed(),
+            };
+            if let Some(buffer) = &render.render.transform_buffer {
+                queue.write_buffer(
+                    buffer,
+                    (instance * std::mem::size_of::<super::transform::TransformRaw>())
+                        as wgpu::BufferAddress,
+                    bytemuck::cast_slice(&[raw]),
+                );
             }
-            layers
-        },
-        tileset: TileSet {
-            image: spritesheet,
-            columns: json_file["tilesets"][0]["columns"].as_u64().unwrap() as u32,
-            tile_count: json_file["tilesets"][0]["tilecount"].as_u64().unwrap() as u32,
-            image_size: (
-                json_file["tilesets"][0]["tilewidth"].as_u64().unwrap() as u32,
-                json_file["tilesets"][0]["tileheight"].as_u64().unwrap() as u32,
-            ),
-        },
-    };
+        }
+        placed
+    }
+
+    /// Rewrites the index of the animated instances whose frame changed at `time`,
+    /// the rest of the instance buffer is left untouched.
+    pub fn animate(&mut self, map: &Map, time: std::time::Duration, queue: &wgpu::Queue) {
+        let Some(buffer) = &self.render.transform_buffer else {
+            return;
+        };
 
-    map
+        let tiles = &map.tilesets[self.tileset].tiles;
+        for animated in &mut self.animated {
+            let Some(frame) = tiles
+                .get(&animated.tile_id)
+                .and_then(|tile| tile.frame(time))
+            else {
+                continue;
+            };
+
+            if frame != animated.frame {
+                animated.frame = frame;
+                queue.write_buffer(
+                    buffer,
+                    (animated.instance as usize
+                        * std::mem::size_of::<super::transform::TransformRaw>()
+                        + std::mem::size_of::<[f32; 16]>())
+                        as wgpu::BufferAddress,
+                    bytemuck::cast_slice(&[frame as i32]),
+                );
+            }
+        }
+    }
 }
 
-pub fn generate_render(
-    id: usize,
-    map: &Map,
-    device: &wgpu::Device,
-    queue: &wgpu::Queue,
-    camera_bind_group_layout: &wgpu::BindGroupLayout,
+/// Pipeline and tileset textures shared by the renders of every tile layer of a map.
+pub struct TileRenderer {
+    /// One per tileset.
+    materials: Vec<super::pipeline::Material>,
+    pipelines: std::rc::Rc<super::pipeline::Pipelines>,
     camera_bind_group: std::rc::Rc<wgpu::BindGroup>,
-    surface_format: &wgpu::TextureFormat,
-) -> super::render::Render {
-    let diffuse_bytes = &map.tileset.image;
-    let diffuse_texture = super::texture::Texture::from_bytes(
-        &device,
-        &queue,
-        &diffuse_bytes,
-        format!("spritesheet{}.png", id).as_str(),
-    )
-    .unwrap();
-    let texture_bind_group_layout: wgpu::BindGroupLayout =
-        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Texture {
-                        multisampled: false,
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                    count: None,
-                },
-            ],
-            label: Some("texture_bind_group_layout"),
-        });
-    let texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        layout: &texture_bind_group_layout,
-        entries: &[
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
+}
+
+impl TileRenderer {
+    pub fn new(
+        map: &Map,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pipelines: std::rc::Rc<super::pipeline::Pipelines>,
+        camera_bind_group: std::rc::Rc<wgpu::BindGroup>,
+        surface_format: &wgpu::TextureFormat,
+    ) -> Result<Self, MapError> {
+        // Layers can be translucent through their opacity and tint
+        let render_pipeline = pipelines.get(
+            device,
+            super::pipeline::PipelineKey {
+                shader: super::pipeline::Shader::Tile,
+                blend: super::pipeline::BlendMode::Alpha,
+                format: *surface_format,
             },
-        ],
-        label: Some("diffuse_bind_group"),
-    });
+        );
 
-    let map_size = map.size.clone();
+        let materials = map
+            .tilesets
+            .iter()
+            .map(|tileset| {
+                let diffuse_texture = super::texture::Texture::from_bytes(
+                    device,
+                    queue,
+                    &tileset.image,
+                    format!("spritesheet_{}.png", tileset.name).as_str(),
+                )
+                .map_err(|error| MapError::Image {
+                    path: tileset.image_path.clone(),
+                    message: error.to_string(),
+                })?;
 
-    let mut map_data = vec![];
-    if let Layers::TileLayer { data, .. } = &map.layers[id] {
-        for i in (0..(map_size.0 * map_size.1) as usize)
-            .step_by(map_size.0 as usize)
-            .rev()
-        {
-            map_data.extend_from_slice(&data[i..(i as u32 + map_size.0) as usize])
+                Ok(super::pipeline::Material {
+                    pipeline: render_pipeline.clone(),
+                    bind_group: pipelines.sprite_sheet_bind_group(
+                        device,
+                        &diffuse_texture,
+                        tileset.sprite_sheet(),
+                    ),
+                })
+            })
+            .collect::<Result<_, MapError>>()?;
+
+        Ok(Self {
+            materials,
+            pipelines,
+            camera_bind_group,
+        })
+    }
+
+    /// Bind group of a `LayerUniform` buffer, passed to `generate_render`.
+    pub fn create_layer_bind_group(
+        &self,
+        device: &wgpu::Device,
+        layer_buffer: &wgpu::Buffer,
+    ) -> std::rc::Rc<wgpu::BindGroup> {
+        self.pipelines.layer_bind_group(device, layer_buffer)
+    }
+
+    /// Builds the renders of the cells `cells` of the tile layer `id`, one per
+    /// tileset used by those cells. Animated tiles start at their first tile,
+    /// see `TileRender::animate`.
+    pub fn generate_render(
+        &self,
+        id: usize,
+        map: &Map,
+        device: &wgpu::Device,
+        mut cells: Vec<(i32, i32)>,
+        layer_bind_group: &std::rc::Rc<wgpu::BindGroup>,
+    ) -> Vec<TileRender> {
+        // Instances grouped by the tileset their GID resolves to
+        let mut tileset_instances: Vec<Vec<super::transform::TransformRaw>> =
+            map.tilesets.iter().map(|_| vec![]).collect();
+        let mut tileset_animated: Vec<Vec<AnimatedInstance>> =
+            map.tilesets.iter().map(|_| vec![]).collect();
+        let mut tileset_cells: Vec<Vec<(i32, i32)>> = map.tilesets.iter().map(|_| vec![]).collect();
+        cells.sort_by_key(|&(x, y)| map.draw_key(x, y));
+        for (x, y) in cells {
+            let Some((tileset_id, local_id, raw, is_animated)) = tile_instance(id, map, x, y)
+            else {
+                continue;
+            };
+
+            if is_animated {
+                tileset_animated[tileset_id].push(AnimatedInstance {
+                    instance: tileset_instances[tileset_id].len() as u32,
+                    tile_id: local_id,
+                    frame: local_id,
+                });
+            }
+            tileset_instances[tileset_id].push(raw);
+            tileset_cells[tileset_id].push((x, y));
         }
+
+        tileset_instances
+            .into_iter()
+            .zip(tileset_animated.into_iter().zip(tileset_cells))
+            .enumerate()
+            .filter(|(_, (instance_data, _))| !instance_data.is_empty())
+            .map(
+                |(tileset_id, (instance_data, (animated, cells)))| TileRender {
+                    animated,
+                    cells,
+                    ..self.create_render(map, tileset_id, &instance_data, device, layer_bind_group)
+                },
+            )
+            .collect()
     }
 
-    let instances = (0..map_size.0 as i32)
-        .flat_map(|x: i32| {
-            let map_data = map_data.clone();
-            (0..map_size.1 as i32).map(move |y| {
+    /// Builds a render showing the tiles `tiles` of the tileset `tileset_id`, each a
+    /// local id and the world position of its center. Used for the editor palette.
+    pub fn generate_palette(
+        &self,
+        map: &Map,
+        tileset_id: usize,
+        tiles: &[(u32, nalgebra_glm::Vec2)],
+        device: &wgpu::Device,
+        layer_bind_group: &std::rc::Rc<wgpu::BindGroup>,
+    ) -> TileRender {
+        let instance_data = tiles
+            .iter()
+            .map(|&(local_id, center)| {
                 let mut t = super::transform::Transform::new();
-                t.translate(&nalgebra_glm::vec3(
-                    x as f32 * map.tile_size.0 as f32 * 2.0,
-                    y as f32 * map.tile_size.1 as f32 * 2.0,
-                    0.0,
-                ));
-                t.label = Some(format!("{}", x + (y * map_size.1 as i32)).to_string());
-                if map_data.len() > 0 {
-                    t.index = map_data[(x + (y * map_size.0 as i32)) as usize];
-                    t.flip_x = 1;
-                }
-                std::rc::Rc::new(std::cell::RefCell::new(t))
+                t.translate(&nalgebra_glm::vec3(center.x, center.y, 0.0));
+                t.index = local_id as i32;
+                t.flip_x = 1;
+                t.to_raw()
             })
-        })
-        .collect::<Vec<_>>();
+            .collect::<Vec<_>>();
+        self.create_render(map, tileset_id, &instance_data, device, layer_bind_group)
+    }
 
-    let instance_data = instances
-        .iter()
-        .map(|x| x.as_ref().borrow().to_raw())
-        .collect::<Vec<_>>();
-
-    let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some("Instance Buffer"),
-        contents: bytemuck::cast_slice(&instance_data),
-        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-    });
-
-    let (vertex_points, vertex_indices) =
-        super::vertex::get_rect(nalgebra_glm::vec3(16.0, 16.0, 0.0));
-    let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: None,
-        contents: bytemuck::cast_slice(&vertex_points),
-        usage: wgpu::BufferUsages::VERTEX,
-    });
-    let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: None,
-        contents: bytemuck::cast_slice(&vertex_indices),
-        usage: wgpu::BufferUsages::INDEX,
-    });
-
-    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-        label: None,
-        source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(include_str!(
-            "shaders/tile.wgsl"
-        ))),
-    });
-
-    let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-        label: Some("Render Pipeline Layout"),
-        bind_group_layouts: &[
-            &texture_bind_group_layout,
-            // &uniform_bind_group_layout,
-            &camera_bind_group_layout,
-        ],
-        push_constant_ranges: &[],
-    });
-
-    let render_pipeline = std::rc::Rc::new(device.create_render_pipeline(
-        &wgpu::RenderPipelineDescriptor {
-            label: None,
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[
-                    wgpu::VertexBufferLayout {
-                        array_stride: std::mem::size_of::<super::vertex::Vertex>()
-                            as wgpu::BufferAddress,
-                        step_mode: wgpu::VertexStepMode::Vertex,
-                        attributes: &[
-                            wgpu::VertexAttribute {
-                                offset: 0,
-                                shader_location: 0,
-                                format: wgpu::VertexFormat::Float32x3,
-                            },
-                            wgpu::VertexAttribute {
-                                offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
-                                shader_location: 1,
-                                format: wgpu::VertexFormat::Float32x2,
-                            },
-                        ],
-                    },
-                    wgpu::VertexBufferLayout {
-                        array_stride: std::mem::size_of::<super::transform::TransformRaw>()
-                            as wgpu::BufferAddress,
-                        step_mode: wgpu::VertexStepMode::Instance,
-                        attributes: &[
-                            wgpu::VertexAttribute {
-                                offset: 0,
-                                shader_location: 5,
-                                format: wgpu::VertexFormat::Float32x4,
-                            },
-                            wgpu::VertexAttribute {
-                                offset: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
-                                shader_location: 6,
-                                format: wgpu::VertexFormat::Float32x4,
-                            },
-                            wgpu::VertexAttribute {
-                                offset: std::mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
-                                shader_location: 7,
-                                format: wgpu::VertexFormat::Float32x4,
-                            },
-                            wgpu::VertexAttribute {
-                                offset: std::mem::size_of::<[f32; 12]>() as wgpu::BufferAddress,
-                                shader_location: 8,
-                                format: wgpu::VertexFormat::Float32x4,
-                            },
-                            wgpu::VertexAttribute {
-                                offset: std::mem::size_of::<[f32; 16]>() as wgpu::BufferAddress,
-                                shader_location: 9,
-                                format: wgpu::VertexFormat::Sint32,
-                            },
-                            wgpu::VertexAttribute {
-                                offset: std::mem::size_of::<[f32; 17]>() as wgpu::BufferAddress,
-                                shader_location: 10,
-                                format: wgpu::VertexFormat::Sint32,
-                            },
-                        ],
-                    },
-                ],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: *surface_format,
-                    blend: Some(wgpu::BlendState {
-                        color: wgpu::BlendComponent::REPLACE,
-                        alpha: wgpu::BlendComponent::OVER,
-                    }),
-                    write_mask: wgpu::ColorWrites::all(),
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                unclipped_depth: false,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-        },
-    ));
-
-    super::render::Render {
-        vertex_buffer,
-        index_buffer,
-        render_pipeline,
-        index_count: vertex_indices.len() as _,
-        transform_buffer: Some(std::rc::Rc::new(instance_buffer)),
-        bind_groups: vec![
-            (0, std::rc::Rc::new(texture_bind_group)),
-            (1, camera_bind_group.clone()),
-        ],
-        instances: instances.len() as u32,
+    /// Render of the instances `instance_data` of the tileset `tileset_id`, without
+    /// animated instances nor cells.
+    fn create_render(
+        &self,
+        map: &Map,
+        tileset_id: usize,
+        instance_data: &[super::transform::TransformRaw],
+        device: &wgpu::Device,
+        layer_bind_group: &std::rc::Rc<wgpu::BindGroup>,
+    ) -> TileRender {
+        let tile_size = map.tilesets[tileset_id].tile_size;
+        TileRender {
+            render: super::render::Render::instanced(
+                device,
+                &self.materials[tileset_id],
+                &[&self.camera_bind_group, layer_bind_group],
+                nalgebra_glm::vec2(tile_size.0 as f32, tile_size.1 as f32),
+                instance_data,
+            ),
+            tileset: tileset_id,
+            animated: Vec::new(),
+            cells: Vec::new(),
+        }
     }
 }
+
+/// Tileset, local id, instance and whether the tile is animated of the cell (x, y)
+/// of the tile layer `id`, `None` when the cell is empty.
+fn tile_instance(
+    id: usize,
+    map: &Map,
+    x: i32,
+    y: i32,
+) -> Option<(usize, u32, super::transform::TransformRaw, bool)> {
+    let tile = map.tile(id, x, y);
+    let (tileset_id, local_id) = map.resolve_gid(tile.gid)?;
+    let tileset = &map.tilesets[tileset_id];
+
+    let center = map.cell_to_world(x, y) + map.tile_anchor(tileset.tile_size);
+    let mut t = super::transform::Transform::new();
+    t.translate(&nalgebra_glm::vec3(center.x, center.y, 0.0));
+    t.index = local_id as i32;
+    t.flip_x = if tile.flip_horizontal { 0 } else { 1 };
+    t.flip_y = tile.flip_vertical as i32;
+    t.flip_diagonal = tile.flip_diagonal as i32;
+
+    let is_animated = tileset
+        .tiles
+        .get(&local_id)
+        .is_some_and(|tile| !tile.animation.is_empty());
+    Some((tileset_id, local_id, t.to_raw(), is_animated))
+}
@@ -0,0 +1,77 @@
+/// Why `load_map` couldn't load a map, or its renders couldn't be built.
+#[derive(Debug)]
+pub enum MapError {
+    /// Reading the file, or fetching it on wasm, failed.
+    Io {
+        path: String,
+        message: String,
+    },
+    Json {
+        path: String,
+        error: serde_json::Error,
+    },
+    Xml {
+        path: String,
+        error: roxmltree::Error,
+    },
+    /// `field` is the JSON path of the value, or the XML element and attribute.
+    MissingField {
+        path: String,
+        field: String,
+    },
+    InvalidField {
+        path: String,
+        field: String,
+        expected: &'static str,
+    },
+    UnsupportedOrientation(String),
+    UnsupportedEncoding(String),
+    InvalidTileData(String),
+    /// A tileset or image layer image couldn't be decoded, when loaded or when
+    /// its texture was built.
+    Image {
+        path: String,
+        message: String,
+    },
+    /// The settings of a `Generator` can't generate a map.
+    InvalidGenerator(String),
+}
+
+impl std::fmt::Display for MapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MapError::Io { path, message } => write!(f, "couldn't read {}: {}", path, message),
+            MapError::Json { path, error } => write!(f, "invalid JSON in {}: {}", path, error),
+            MapError::Xml { path, error } => write!(f, "invalid XML in {}: {}", path, error),
+            MapError::MissingField { path, field } => {
+                write!(f, "missing field {} in {}", field, path)
+            }
+            MapError::InvalidField {
+                path,
+                field,
+                expected,
+            } => write!(f, "field {} in {} should be {}", field, path, expected),
+            MapError::UnsupportedOrientation(orientation) => {
+                write!(f, "unsupported map orientation {}", orientation)
+            }
+            MapError::UnsupportedEncoding(encoding) => {
+                write!(f, "unsupported tile layer encoding {}", encoding)
+            }
+            MapError::InvalidTileData(message) => write!(f, "invalid tile layer data: {}", message),
+            MapError::Image { path, message } => {
+                write!(f, "invalid image {}: {}", path, message)
+            }
+            MapError::InvalidGenerator(message) => write!(f, "invalid map generator: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for MapError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MapError::Json { error, .. } => Some(error),
+            MapError::Xml { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,563 @@
+use super::{
+    LayerAttributes, Layers, Map, MapError, Object, Orientation, Properties, PropertyValue,
+    RenderOrder, Shape, Tile, TileSet, WangColor, WangSet, WangSetType,
+};
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+
+/// Object the player starts at, on the largest group of islands joined by bridges.
+const SPAWN_POINT: &str = "spawn_point";
+
+/// Colors of `Generator::coast`, water first so the shore is drawn on the land.
+const COAST_WATER: u8 = 1;
+const COAST_LAND: u8 = 2;
+
+/// Ids, local to the tileset, of the tiles `Generator` places. Blocks are given
+/// by their top-left tile. The defaults are the sunnyside tiles of `mapa.json`.
+#[derive(Debug, Clone)]
+pub struct GeneratorTiles {
+    /// Block of water tiles repeated all over the map.
+    pub water: u32,
+    pub water_size: (u32, u32),
+    /// 3×3 block of the shore of an island, its center is replaced with `grass`.
+    /// The shore is autotiled with them, see `Generator::coast`.
+    pub island: u32,
+    pub grass: u32,
+    /// Block 3 tiles wide of the cliff rows under an island.
+    pub cliff: u32,
+    pub cliff_rows: u32,
+    /// 3×3 block stretched over a bridge, walked along its middle.
+    pub bridge: u32,
+    /// Placed on the collision layer around the cells that can be walked on.
+    pub collision: u32,
+}
+
+impl Default for GeneratorTiles {
+    fn default() -> Self {
+        Self {
+            water: 1163,
+            water_size: (4, 4),
+            island: 1793,
+            grass: 66,
+            cliff: 1985,
+            cliff_rows: 2,
+            bridge: 743,
+            collision: 70,
+        }
+    }
+}
+
+/// Generates island maps like `mapa.json`: an island in each leaf of a binary
+/// space partition of the map, with bridges joining them. The same seed always
+/// generates the same map.
+#[derive(Debug, Clone)]
+pub struct Generator {
+    pub seed: u64,
+    /// In tiles.
+    pub size: (u32, u32),
+    /// Smallest width and height of an island, shore included but not its cliff. At least 3.
+    pub min_island: u32,
+    /// Image of the tileset, relative to the working directory.
+    pub tileset: String,
+    pub tile_size: (u32, u32),
+    pub tiles: GeneratorTiles,
+}
+
+impl Default for Generator {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            size: (60, 40),
+            min_island: 5,
+            tileset: "./resources/spr_tileset_sunnysideworld_16px.png".to_string(),
+            tile_size: (16, 16),
+            tiles: GeneratorTiles::default(),
+        }
+    }
+}
+
+/// Cells `x..x + width` by `y..y + height`.
+#[derive(Debug, Clone, Copy)]
+struct Rect {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+}
+
+impl Rect {
+    fn right(&self) -> i32 {
+        self.x + self.width
+    }
+
+    fn bottom(&self) -> i32 {
+        self.y + self.height
+    }
+
+    fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && x < self.right() && y >= self.y && y < self.bottom()
+    }
+
+    fn intersects(&self, other: &Rect) -> bool {
+        self.x < other.right()
+            && other.x < self.right()
+            && self.y < other.bottom()
+            && other.y < self.bottom()
+    }
+
+    fn expand(&self, cells: i32) -> Rect {
+        Rect {
+            x: self.x - cells,
+            y: self.y - cells,
+            width: self.width + cells * 2,
+            height: self.height + cells * 2,
+        }
+    }
+
+    /// Offset in a 3×3 block of the tile at `(x, y)`, stretching its middle over the rect.
+    fn nine_slice(&self, x: i32, y: i32) -> (u32, u32) {
+        let slice = |position: i32, start: i32, end: i32| match position {
+            _ if position == start => 0,
+            _ if position == end - 1 => 2,
+            _ => 1,
+        };
+        (
+            slice(x, self.x, self.right()),
+            slice(y, self.y, self.bottom()),
+        )
+    }
+}
+
+impl Generator {
+    pub async fn generate(&self) -> Result<Map, MapError> {
+        self.validate()?;
+        let (image, image_size) = super::load_image(&self.tileset).await?;
+        let columns = image_size.0 / self.tile_size.0;
+        let mut tileset = TileSet {
+            name: std::path::Path::new(&self.tileset)
+                .file_stem()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            first_gid: 1,
+            image,
+            image_path: self.tileset.clone(),
+            columns,
+            tile_count: columns * (image_size.1 / self.tile_size.1),
+            tile_size: self.tile_size,
+            margin: 0,
+            spacing: 0,
+            image_size,
+            properties: Properties::new(),
+            tiles: Default::default(),
+            wang_sets: Vec::new(),
+        };
+
+        // ChaCha rather than `StdRng`, whose algorithm may change between rand versions
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(self.seed);
+        let mut leaves = Vec::new();
+        self.partition(
+            Rect {
+                x: 0,
+                y: 0,
+                width: self.size.0 as i32,
+                height: self.size.1 as i32,
+            },
+            &mut rng,
+            &mut leaves,
+        );
+        let islands = leaves
+            .iter()
+            .map(|leaf| self.island(leaf, &mut rng))
+            .collect::<Vec<_>>();
+        let (bridges, groups) = self.bridges(&islands, &mut rng);
+
+        tileset.wang_sets.push(self.coast(columns));
+        let width = self.size.0 as i32;
+        let mask = (0..width * self.size.1 as i32)
+            .map(|cell| {
+                let (x, y) = (cell % width, cell / width);
+                match islands.iter().any(|island| island.contains(x, y)) {
+                    true => COAST_LAND,
+                    false => COAST_WATER,
+                }
+            })
+            .collect::<Vec<_>>();
+        let shore = tileset.autotile(0, &mask, width as usize);
+
+        let spawn = {
+            let largest = (0..islands.len())
+                .max_by_key(|&group| groups.iter().filter(|&&other| other == group).count())
+                .unwrap_or(0);
+            let candidates = (0..islands.len())
+                .filter(|&island| groups[island] == largest)
+                .collect::<Vec<_>>();
+            let island = islands[*candidates.choose(&mut rng).unwrap_or(&0)];
+            (island.x + island.width / 2, island.y + island.height / 2)
+        };
+
+        Ok(Map {
+            size: self.size,
+            infinite: false,
+            orientation: Orientation::Orthogonal,
+            render_order: RenderOrder::RightDown,
+            tile_size: self.tile_size,
+            parallax_origin: (0.0, 0.0),
+            // As a string, a seed doesn't fit the i64 of an int property
+            properties: Properties::from([(
+                "seed".to_string(),
+                PropertyValue::String(self.seed.to_string()),
+            )]),
+            layers: vec![
+                self.tile_layer(1, "water", true, |x, y| {
+                    let (width, height) = self.tiles.water_size;
+                    Some(self.tiles.water + x as u32 % width + y as u32 % height * columns)
+                }),
+                self.tile_layer(2, "islands", true, |x, y| {
+                    let island = islands.iter().find(|island| {
+                        island.contains(x, y)
+                            || (island.x..island.right()).contains(&x)
+                                && (island.bottom()..island.bottom() + self.tiles.cliff_rows as i32)
+                                    .contains(&y)
+                    })?;
+                    let (dx, _) = island.nine_slice(x, y);
+                    match y >= island.bottom() {
+                        true => {
+                            Some(self.tiles.cliff + dx + (y - island.bottom()) as u32 * columns)
+                        }
+                        false => match shore[(x + y * width) as usize].gid {
+                            0 => None,
+                            gid => Some(gid - tileset.first_gid),
+                        },
+                    }
+                }),
+                self.tile_layer(3, "bridges", true, |x, y| {
+                    let bridge = bridges.iter().find(|bridge| bridge.contains(x, y))?;
+                    let (dx, dy) = bridge.nine_slice(x, y);
+                    Some(self.tiles.bridge + dx + dy * columns)
+                }),
+                Layers::ObjectGroup {
+                    id: 4,
+                    name: "objects".to_string(),
+                    visible: false,
+                    attributes: LayerAttributes::default(),
+                    properties: Properties::new(),
+                    objects: vec![Object {
+                        name: SPAWN_POINT.to_string(),
+                        id: 1,
+                        class: String::new(),
+                        position: (
+                            (spawn.0 as f32 + 0.5) * self.tile_size.0 as f32,
+                            (spawn.1 as f32 + 0.5) * self.tile_size.1 as f32,
+                        ),
+                        size: (0.0, 0.0),
+                        rotation: 0.0,
+                        visible: true,
+                        shape: Shape::Point,
+                        properties: Properties::new(),
+                    }],
+                },
+                {
+                    let walkable = |x: i32, y: i32| {
+                        islands.iter().any(|island| island.contains(x, y))
+                            || bridges.iter().any(|bridge| {
+                                bridge.contains(x, y)
+                                    && match bridge.width > bridge.height {
+                                        true => y == bridge.y + 1,
+                                        false => x == bridge.x + 1,
+                                    }
+                            })
+                    };
+                    let mut layer = self.tile_layer(5, "colisiones", false, |x, y| {
+                        let blocks = !walkable(x, y)
+                            && [(-1, 0), (1, 0), (0, -1), (0, 1)]
+                                .iter()
+                                .any(|(dx, dy)| walkable(x + dx, y + dy));
+                        blocks.then_some(self.tiles.collision)
+                    });
+                    if let Layers::TileLayer { properties, .. } = &mut layer {
+                        properties.insert("collision".to_string(), PropertyValue::Bool(true));
+                    }
+                    layer
+                },
+            ],
+            tilesets: vec![tileset],
+        })
+    }
+
+    /// Corner wang set of the shore, with water and land colors. Land cells next
+    /// to water take the shore tile of the side the water is on.
+    fn coast(&self, columns: u32) -> WangSet {
+        let color = |name: &str, color: [f32; 4]| WangColor {
+            name: name.to_string(),
+            class: String::new(),
+            color,
+            tile: None,
+            probability: 1.0,
+            properties: Properties::new(),
+        };
+        let tiles = (0..3)
+            .flat_map(|dy| (0..3).map(move |dx| (dx, dy)))
+            .map(|(dx, dy)| {
+                let id = match (dx, dy) {
+                    (1, 1) => self.tiles.grass,
+                    _ => self.tiles.island + dx + dy * columns,
+                };
+                // A corner is land when it points to the middle of the block
+                let corner = |land: bool| if land { COAST_LAND } else { COAST_WATER };
+                let (left, right, top, bottom) = (dx > 0, dx < 2, dy > 0, dy < 2);
+                let wang_id = [
+                    0,
+                    corner(right && top),
+                    0,
+                    corner(right && bottom),
+                    0,
+                    corner(left && bottom),
+                    0,
+                    corner(left && top),
+                ];
+                (id, wang_id)
+            })
+            .collect();
+
+        WangSet {
+            name: "coast".to_string(),
+            class: String::new(),
+            kind: WangSetType::Corner,
+            tile: Some(self.tiles.grass),
+            colors: vec![
+                color("water", [0.2, 0.5, 0.9, 1.0]),
+                color("land", [0.3, 0.8, 0.3, 1.0]),
+            ],
+            tiles,
+            properties: Properties::new(),
+        }
+    }
+
+    /// Checks the map fits an island at least, and that the tiles have a size.
+    fn validate(&self) -> Result<(), MapError> {
+        let invalid = |message: String| Err(MapError::InvalidGenerator(message));
+        if self.min_island < 3 {
+            return invalid(format!("min_island {} is under 3", self.min_island));
+        }
+        if self.tile_size.0 == 0 || self.tile_size.1 == 0 {
+            return invalid(format!("tile_size {:?} is empty", self.tile_size));
+        }
+        if self.tiles.water_size.0 == 0 || self.tiles.water_size.1 == 0 {
+            return invalid(format!("water_size {:?} is empty", self.tiles.water_size));
+        }
+        // The partition never leaves less room than this for an island
+        let min = (
+            self.min_island + 2,
+            self.min_island + self.tiles.cliff_rows + 2,
+        );
+        if self.size.0 < min.0 || self.size.1 < min.1 {
+            return invalid(format!(
+                "size {:?} is smaller than the {:?} an island takes",
+                self.size, min
+            ));
+        }
+        Ok(())
+    }
+
+    /// Splits `area` until its parts are too small to hold two islands, or at
+    /// random once they are small enough, so the islands vary in size.
+    fn partition(&self, area: Rect, rng: &mut impl Rng, leaves: &mut Vec<Rect>) {
+        // Room for the smallest island, its cliff and a tile of water around them
+        let min = (
+            self.min_island as i32 + 2,
+            self.min_island as i32 + self.tiles.cliff_rows as i32 + 2,
+        );
+        let split_x = area.width >= min.0 * 2;
+        let split_y = area.height >= min.1 * 2;
+        let small = area.width < min.0 * 3 && area.height < min.1 * 3;
+        if !(split_x || split_y) || small && rng.gen_bool(0.3) {
+            leaves.push(area);
+            return;
+        }
+
+        let (first, second) =
+            match split_x && (!split_y || area.width * min.1 >= area.height * min.0) {
+                true => {
+                    let width = rng.gen_range(min.0..=area.width - min.0);
+                    (
+                        Rect { width, ..area },
+                        Rect {
+                            x: area.x + width,
+                            width: area.width - width,
+                            ..area
+                        },
+                    )
+                }
+                false => {
+                    let height = rng.gen_range(min.1..=area.height - min.1);
+                    (
+                        Rect { height, ..area },
+                        Rect {
+                            y: area.y + height,
+                            height: area.height - height,
+                            ..area
+                        },
+                    )
+                }
+            };
+        self.partition(first, rng, leaves);
+        self.partition(second, rng, leaves);
+    }
+
+    /// Island in `leaf`, at least two thirds of it so islands next to each other line up for bridges.
+    fn island(&self, leaf: &Rect, rng: &mut impl Rng) -> Rect {
+        let room = (
+            leaf.width - 2,
+            leaf.height - 2 - self.tiles.cliff_rows as i32,
+        );
+        let min_island = self.min_island as i32;
+        let width = rng.gen_range(min_island.max(room.0 * 2 / 3)..=room.0);
+        let height = rng.gen_range(min_island.max(room.1 * 2 / 3)..=room.1);
+        Rect {
+            x: leaf.x + 1 + rng.gen_range(0..=room.0 - width),
+            y: leaf.y + 1 + rng.gen_range(0..=room.1 - height),
+            width,
+            height,
+        }
+    }
+
+    /// Shortest bridges joining the islands without crossing others, along with the
+    /// group of islands each island ends up in.
+    fn bridges(&self, islands: &[Rect], rng: &mut impl Rng) -> (Vec<Rect>, Vec<usize>) {
+        let footprint = |island: &Rect| Rect {
+            height: island.height + self.tiles.cliff_rows as i32,
+            ..*island
+        };
+        let mut candidates = (0..islands.len())
+            .flat_map(|a| (0..islands.len()).map(move |b| (a, b)))
+            .filter_map(|(a, b)| Some((a, b, self.bridge(&islands[a], &islands[b])?)))
+            .filter(|(a, b, bridge)| {
+                islands.iter().enumerate().all(|(other, island)| {
+                    other == *a || other == *b || !footprint(island).intersects(&bridge.expand(1))
+                })
+            })
+            .collect::<Vec<_>>();
+        // The sort is stable, bridges as long as each other are taken in the shuffled order
+        candidates.shuffle(rng);
+        candidates.sort_by_key(|(_, _, bridge)| bridge.width.max(bridge.height));
+
+        fn root(groups: &mut [usize], island: usize) -> usize {
+            match groups[island] {
+                parent if parent == island => island,
+                parent => {
+                    let root = root(groups, parent);
+                    groups[island] = root;
+                    root
+                }
+            }
+        }
+        let mut groups = (0..islands.len()).collect::<Vec<_>>();
+        let mut bridges = Vec::<Rect>::new();
+        for (a, b, bridge) in candidates {
+            let (a, b) = (root(&mut groups, a), root(&mut groups, b));
+            if a != b
+                && !bridges
+                    .iter()
+                    .any(|other| other.expand(1).intersects(&bridge))
+            {
+                groups[a] = b;
+                bridges.push(bridge);
+            }
+        }
+        let groups = (0..islands.len())
+            .map(|island| root(&mut groups, island))
+            .collect();
+        (bridges, groups)
+    }
+
+    /// Straight bridge from `a` to `b` when `b` is right of or under `a`, landing
+    /// past the shore of both.
+    fn bridge(&self, a: &Rect, b: &Rect) -> Option<Rect> {
+        // The bridge is 3 tiles wide, over the interior of the islands
+        let overlap = |start: i32, end: i32| (end - start >= 3).then_some((start + end - 3) / 2);
+        if a.right() < b.x {
+            let y = overlap(a.y.max(b.y) + 1, a.bottom().min(b.bottom()) - 1)?;
+            return Some(Rect {
+                x: a.right() - 1,
+                y,
+                width: b.x - a.right() + 2,
+                height: 3,
+            });
+        }
+        if a.bottom() + self.tiles.cliff_rows as i32 <= b.y {
+            let x = overlap(a.x.max(b.x) + 1, a.right().min(b.right()) - 1)?;
+            return Some(Rect {
+                x,
+                y: a.bottom() - 1,
+                width: 3,
+                height: b.y - a.bottom() + 2,
+            });
+        }
+        None
+    }
+
+    /// Tile layer with `tile(x, y)`, a local id in the only tileset, in each cell that has one.
+    fn tile_layer(
+        &self,
+        id: u32,
+        name: &str,
+        visible: bool,
+        tile: impl Fn(i32, i32) -> Option<u32>,
+    ) -> Layers {
+        Layers::TileLayer {
+            id,
+            name: name.to_string(),
+            visible,
+            attributes: LayerAttributes::default(),
+            properties: Properties::new(),
+            data: (0..self.size.1 as i32)
+                .flat_map(|y| (0..self.size.0 as i32).map(move |x| (x, y)))
+                .map(|(x, y)| Tile {
+                    gid: tile(x, y).map(|id| id + 1).unwrap_or(0),
+                    ..Default::default()
+                })
+                .collect(),
+            chunks: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn same_seed_generates_same_map() {
+        let generator = super::Generator {
+            seed: 7,
+            ..Default::default()
+        };
+        let map = pollster::block_on(generator.generate()).unwrap();
+        assert_eq!(map, pollster::block_on(generator.generate()).unwrap());
+
+        let other = super::Generator {
+            seed: 8,
+            ..Default::default()
+        };
+        assert_ne!(map, pollster::block_on(other.generate()).unwrap());
+    }
+
+    #[test]
+    fn tiny_maps_are_rejected() {
+        for size in [(0, 0), (6, 20), (20, 8)] {
+            let generator = super::Generator {
+                size,
+                ..Default::default()
+            };
+            assert!(matches!(
+                pollster::block_on(generator.generate()),
+                Err(super::MapError::InvalidGenerator(_))
+            ));
+        }
+
+        // Just room for one island
+        let generator = super::Generator {
+            size: (7, 9),
+            ..Default::default()
+        };
+        let map = pollster::block_on(generator.generate()).unwrap();
+        assert_eq!(map.size, (7, 9));
+    }
+}
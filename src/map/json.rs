@@ -0,0 +1,545 @@
+use super::{
+    decode_tile_data, load_bytes, load_image, parse_color, resolve_path, tmx, Chunk, Frame,
+    LayerAttributes, Layers, Map, MapError, Object, Orientation, Properties, PropertyValue,
+    RenderOrder, Shape, Tile, TileData, TileSet, WangColor, WangSet, WangSetType,
+};
+
+/// A JSON value along with where it comes from, so errors can point at it.
+#[derive(Clone)]
+pub(super) struct Json<'a> {
+    pub(super) file: &'a str,
+    pub(super) path: String,
+    pub(super) value: &'a serde_json::Value,
+}
+
+impl<'a> Json<'a> {
+    pub(super) fn get(&self, key: &str) -> Json<'a> {
+        Json {
+            file: self.file,
+            path: format!("{}.{}", self.path, key),
+            value: &self.value[key],
+        }
+    }
+
+    pub(super) fn is_null(&self) -> bool {
+        self.value.is_null()
+    }
+
+    pub(super) fn typed<T>(
+        &self,
+        expected: &'static str,
+        f: impl FnOnce(&'a serde_json::Value) -> Option<T>,
+    ) -> Result<T, MapError> {
+        if self.is_null() {
+            return Err(MapError::MissingField {
+                path: self.file.to_string(),
+                field: self.path.clone(),
+            });
+        }
+
+        f(self.value).ok_or_else(|| self.invalid(expected))
+    }
+
+    pub(super) fn invalid(&self, expected: &'static str) -> MapError {
+        MapError::InvalidField {
+            path: self.file.to_string(),
+            field: self.path.clone(),
+            expected,
+        }
+    }
+
+    /// Like the typed getters, but missing values fall back to `default`.
+    pub(super) fn or<T>(
+        &self,
+        default: T,
+        f: fn(&Self) -> Result<T, MapError>,
+    ) -> Result<T, MapError> {
+        match self.is_null() {
+            true => Ok(default),
+            false => f(self),
+        }
+    }
+
+    pub(super) fn u32(&self) -> Result<u32, MapError> {
+        self.typed("an unsigned integer", |value| {
+            value.as_u64().and_then(|value| u32::try_from(value).ok())
+        })
+    }
+
+    pub(super) fn i32(&self) -> Result<i32, MapError> {
+        self.typed("an integer", |value| {
+            value.as_i64().and_then(|value| i32::try_from(value).ok())
+        })
+    }
+
+    pub(super) fn i64(&self) -> Result<i64, MapError> {
+        self.typed("an integer", serde_json::Value::as_i64)
+    }
+
+    pub(super) fn f64(&self) -> Result<f64, MapError> {
+        self.typed("a number", serde_json::Value::as_f64)
+    }
+
+    pub(super) fn f32(&self) -> Result<f32, MapError> {
+        self.typed("a number", |value| value.as_f64().map(|value| value as f32))
+    }
+
+    pub(super) fn bool(&self) -> Result<bool, MapError> {
+        self.typed("a boolean", serde_json::Value::as_bool)
+    }
+
+    pub(super) fn str(&self) -> Result<&'a str, MapError> {
+        self.typed("a string", serde_json::Value::as_str)
+    }
+
+    pub(super) fn color(&self) -> Result<[f32; 4], MapError> {
+        self.typed("a #RRGGBB or #AARRGGBB color", |value| {
+            value.as_str().and_then(parse_color)
+        })
+    }
+
+    pub(super) fn entries(&self) -> Result<Vec<(&'a str, Json<'a>)>, MapError> {
+        let values = self.typed("an object", serde_json::Value::as_object)?;
+        Ok(values
+            .iter()
+            .map(|(key, value)| {
+                (
+                    key.as_str(),
+                    Json {
+                        file: self.file,
+                        path: format!("{}.{}", self.path, key),
+                        value,
+                    },
+                )
+            })
+            .collect())
+    }
+
+    pub(super) fn array(&self) -> Result<Vec<Json<'a>>, MapError> {
+        let values = self.typed("an array", serde_json::Value::as_array)?;
+        Ok(values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| Json {
+                file: self.file,
+                path: format!("{}[{}]", self.path, i),
+                value,
+            })
+            .collect())
+    }
+
+    /// First two values of an array, like the `[x, y]` positions of LDtk.
+    pub(super) fn pair(&self, expected: &'static str) -> Result<(Json<'a>, Json<'a>), MapError> {
+        let values = self.array()?;
+        match (values.first(), values.get(1)) {
+            (Some(x), Some(y)) => Ok((x.clone(), y.clone())),
+            _ => Err(self.invalid(expected)),
+        }
+    }
+}
+
+pub(super) async fn load_json(path: &str) -> Result<serde_json::Value, MapError> {
+    serde_json::from_slice::<serde_json::Value>(&load_bytes(path).await?).map_err(|error| {
+        MapError::Json {
+            path: path.to_string(),
+            error,
+        }
+    })
+}
+
+pub async fn load_map(path_data: &str) -> Result<Map, MapError> {
+    parse_map(path_data, &load_json(path_data).await?).await
+}
+
+/// Parses a map read from `path_data`, the paths in it are relative to that file.
+pub async fn parse_map(path_data: &str, value: &serde_json::Value) -> Result<Map, MapError> {
+    let json_file = Json {
+        file: path_data,
+        path: "$".to_string(),
+        value,
+    };
+
+    let orientation = Orientation::parse(
+        json_file.get("orientation").or("orthogonal", Json::str)?,
+        json_file.get("staggeraxis").or("y", Json::str)?,
+        json_file.get("staggerindex").or("odd", Json::str)?,
+        json_file.get("hexsidelength").or(0, Json::u32)?,
+    )?;
+
+    let mut tilesets = vec![];
+    for value in json_file.get("tilesets").array()? {
+        let first_gid = value.get("firstgid").u32()?;
+        let tileset = match value.get("source").or("", Json::str)? {
+            "" => parse_tileset(&value, first_gid).await?,
+            source => {
+                let source = resolve_path(path_data, source);
+                if source.ends_with(".tsx") {
+                    tmx::load_tileset(&source, first_gid).await?
+                } else {
+                    let value = load_json(&source).await?;
+                    let json = Json {
+                        file: &source,
+                        path: "$".to_string(),
+                        value: &value,
+                    };
+                    parse_tileset(&json, first_gid).await?
+                }
+            }
+        };
+        tilesets.push(tileset);
+    }
+    tilesets.sort_by_key(|tileset| tileset.first_gid);
+
+    let layers = parse_layers(json_file.clone()).await?;
+
+    Ok(Map {
+        infinite: json_file.get("infinite").or(false, Json::bool)?,
+        orientation,
+        render_order: RenderOrder::parse(json_file.get("renderorder").or("right-down", Json::str)?),
+        size: (
+            json_file.get("width").u32()?,
+            json_file.get("height").u32()?,
+        ),
+        tile_size: (
+            json_file.get("tilewidth").u32()?,
+            json_file.get("tileheight").u32()?,
+        ),
+        parallax_origin: (
+            json_file.get("parallaxoriginx").or(0.0, Json::f32)?,
+            json_file.get("parallaxoriginy").or(0.0, Json::f32)?,
+        ),
+        properties: parse_properties(&json_file)?,
+        layers,
+        tilesets,
+    })
+}
+
+/// The `layers` array of a map or group, groups are parsed recursively.
+fn parse_layers(
+    value: Json<'_>,
+) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Vec<Layers>, MapError>> + '_>> {
+    Box::pin(async move {
+        let mut layers = vec![];
+        for value in value.get("layers").array()? {
+            let id = value.get("id").u32()?;
+            let name = value.get("name").str()?.to_string();
+            let visible = value.get("visible").or(true, Json::bool)?;
+            let attributes = parse_attributes(&value)?;
+            let properties = parse_properties(&value)?;
+
+            layers.push(match value.get("type").str()? {
+                "tilelayer" => {
+                    let data = value.get("data");
+                    Layers::TileLayer {
+                        id,
+                        name,
+                        visible,
+                        attributes,
+                        properties,
+                        data: match data.is_null() {
+                            true => vec![],
+                            false => parse_tile_data(&value, &data)?,
+                        },
+                        chunks: value
+                            .get("chunks")
+                            .or(vec![], Json::array)?
+                            .iter()
+                            .map(|chunk| {
+                                Ok(Chunk {
+                                    position: (chunk.get("x").i32()?, chunk.get("y").i32()?),
+                                    size: (chunk.get("width").u32()?, chunk.get("height").u32()?),
+                                    data: parse_tile_data(&value, &chunk.get("data"))?,
+                                })
+                            })
+                            .collect::<Result<Vec<Chunk>, MapError>>()?,
+                    }
+                }
+                "objectgroup" => Layers::ObjectGroup {
+                    id,
+                    name,
+                    visible,
+                    attributes,
+                    properties,
+                    objects: value
+                        .get("objects")
+                        .array()?
+                        .iter()
+                        .map(parse_object)
+                        .collect::<Result<Vec<Object>, MapError>>()?,
+                },
+                "imagelayer" => {
+                    let image_path = match value.get("image").or("", Json::str)? {
+                        "" => String::new(),
+                        image => resolve_path(value.file, image),
+                    };
+                    let (image, image_size) = match image_path.as_str() {
+                        "" => (vec![], (0, 0)),
+                        image_path => load_image(image_path).await?,
+                    };
+                    Layers::ImageLayer {
+                        id,
+                        name,
+                        visible,
+                        attributes,
+                        properties,
+                        image,
+                        image_path,
+                        image_size,
+                        repeat: (
+                            value.get("repeatx").or(false, Json::bool)?,
+                            value.get("repeaty").or(false, Json::bool)?,
+                        ),
+                    }
+                }
+                "group" => Layers::Group {
+                    id,
+                    name,
+                    visible,
+                    attributes,
+                    properties,
+                    layers: parse_layers(value.clone()).await?,
+                },
+                _ => return Err(value.get("type").invalid("a layer type")),
+            });
+        }
+        Ok(layers)
+    })
+}
+
+fn parse_attributes(value: &Json) -> Result<LayerAttributes, MapError> {
+    let default = LayerAttributes::default();
+    Ok(LayerAttributes {
+        opacity: value.get("opacity").or(default.opacity, Json::f32)?,
+        tint_color: value.get("tintcolor").or(default.tint_color, Json::color)?,
+        offset: (
+            value.get("offsetx").or(default.offset.0, Json::f32)?,
+            value.get("offsety").or(default.offset.1, Json::f32)?,
+        ),
+        parallax: (
+            value.get("parallaxx").or(default.parallax.0, Json::f32)?,
+            value.get("parallaxy").or(default.parallax.1, Json::f32)?,
+        ),
+    })
+}
+
+/// The `properties` array of a map, layer, tileset, tile or object.
+fn parse_properties(value: &Json) -> Result<Properties, MapError> {
+    value
+        .get("properties")
+        .or(vec![], Json::array)?
+        .iter()
+        .map(|property| {
+            let value = property.get("value");
+            let property_value = match property.get("type").or("string", Json::str)? {
+                "string" => PropertyValue::String(value.str()?.to_string()),
+                "int" => PropertyValue::Int(value.i64()?),
+                "float" => PropertyValue::Float(value.f64()?),
+                "bool" => PropertyValue::Bool(value.bool()?),
+                "color" => PropertyValue::Color(match value.str()? {
+                    "" => None,
+                    _ => Some(value.color()?),
+                }),
+                "file" => PropertyValue::File(value.str()?.to_string()),
+                "object" => PropertyValue::Object(value.u32()?),
+                "class" => PropertyValue::Class {
+                    class: property.get("propertytype").or("", Json::str)?.to_string(),
+                    properties: parse_members(&value)?,
+                },
+                _ => return Err(property.get("type").invalid("a property type")),
+            };
+            Ok((property.get("name").str()?.to_string(), property_value))
+        })
+        .collect()
+}
+
+/// Members of a class property, their types aren't stored in the map so they
+/// are guessed from the JSON values.
+fn parse_members(value: &Json) -> Result<Properties, MapError> {
+    value
+        .or(vec![], Json::entries)?
+        .into_iter()
+        .map(|(name, member)| {
+            let member = match member.value {
+                serde_json::Value::Bool(value) => PropertyValue::Bool(*value),
+                serde_json::Value::Number(_) => match member.value.as_i64() {
+                    Some(value) => PropertyValue::Int(value),
+                    None => PropertyValue::Float(member.f64()?),
+                },
+                serde_json::Value::Object(_) => PropertyValue::Class {
+                    class: String::new(),
+                    properties: parse_members(&member)?,
+                },
+                _ => PropertyValue::String(member.str()?.to_string()),
+            };
+            Ok((name.to_string(), member))
+        })
+        .collect()
+}
+
+/// `layer` holds the encoding of `data`, which is either the layer or one of its chunks data.
+fn parse_tile_data(layer: &Json, data: &Json) -> Result<Vec<Tile>, MapError> {
+    match layer.get("encoding").or("csv", Json::str)? {
+        "base64" => decode_tile_data(data.str()?, layer.get("compression").or("", Json::str)?),
+        "csv" => data
+            .array()?
+            .iter()
+            .map(|gid| Ok(Tile::from_raw(gid.u32()?)))
+            .collect::<Result<Vec<Tile>, MapError>>(),
+        encoding => Err(MapError::UnsupportedEncoding(encoding.to_string())),
+    }
+}
+
+/// The tileset image is relative to the file the tileset was read from.
+async fn parse_tileset(value: &Json<'_>, first_gid: u32) -> Result<TileSet, MapError> {
+    let image_path = resolve_path(value.file, value.get("image").str()?);
+    Ok(TileSet {
+        name: value.get("name").or("", Json::str)?.to_string(),
+        first_gid,
+        image: load_image(&image_path).await?.0,
+        image_path,
+        columns: value.get("columns").u32()?,
+        tile_count: value.get("tilecount").u32()?,
+        tile_size: (
+            value.get("tilewidth").u32()?,
+            value.get("tileheight").u32()?,
+        ),
+        margin: value.get("margin").or(0, Json::u32)?,
+        spacing: value.get("spacing").or(0, Json::u32)?,
+        image_size: (
+            value.get("imagewidth").u32()?,
+            value.get("imageheight").u32()?,
+        ),
+        properties: parse_properties(value)?,
+        tiles: value
+            .get("tiles")
+            .or(vec![], Json::array)?
+            .iter()
+            .map(|tile| {
+                Ok((
+                    tile.get("id").u32()?,
+                    TileData {
+                        properties: parse_properties(tile)?,
+                        animation: tile
+                            .get("animation")
+                            .or(vec![], Json::array)?
+                            .iter()
+                            .map(|frame| {
+                                Ok(Frame {
+                                    tile_id: frame.get("tileid").u32()?,
+                                    duration: std::time::Duration::from_millis(
+                                        frame.get("duration").u32()? as u64,
+                                    ),
+                                })
+                            })
+                            .collect::<Result<_, MapError>>()?,
+                        objects: tile
+                            .get("objectgroup")
+                            .get("objects")
+                            .or(vec![], Json::array)?
+                            .iter()
+                            .map(parse_object)
+                            .collect::<Result<_, MapError>>()?,
+                    },
+                ))
+            })
+            .collect::<Result<_, MapError>>()?,
+        wang_sets: value
+            .get("wangsets")
+            .or(vec![], Json::array)?
+            .iter()
+            .map(parse_wang_set)
+            .collect::<Result<_, MapError>>()?,
+    })
+}
+
+fn parse_wang_set(value: &Json) -> Result<WangSet, MapError> {
+    let kind = value.get("type");
+    Ok(WangSet {
+        name: value.get("name").or("", Json::str)?.to_string(),
+        class: value.get("class").or("", Json::str)?.to_string(),
+        kind: WangSetType::parse(kind.or("corner", Json::str)?)
+            .ok_or_else(|| kind.invalid("corner, edge or mixed"))?,
+        // -1 when the set has no tile
+        tile: u32::try_from(value.get("tile").or(-1, Json::i64)?).ok(),
+        colors: value
+            .get("colors")
+            .or(vec![], Json::array)?
+            .iter()
+            .map(|color| {
+                Ok(WangColor {
+                    name: color.get("name").or("", Json::str)?.to_string(),
+                    class: color.get("class").or("", Json::str)?.to_string(),
+                    color: color.get("color").color()?,
+                    tile: u32::try_from(color.get("tile").or(-1, Json::i64)?).ok(),
+                    probability: color.get("probability").or(1.0, Json::f32)?,
+                    properties: parse_properties(color)?,
+                })
+            })
+            .collect::<Result<_, MapError>>()?,
+        tiles: value
+            .get("wangtiles")
+            .or(vec![], Json::array)?
+            .iter()
+            .map(|tile| {
+                let wang_id = tile.get("wangid");
+                let colors = wang_id
+                    .array()?
+                    .iter()
+                    .map(|color| {
+                        u8::try_from(color.u32()?).map_err(|_| color.invalid("a wang color"))
+                    })
+                    .collect::<Result<Vec<_>, MapError>>()?;
+                Ok((
+                    tile.get("tileid").u32()?,
+                    colors
+                        .try_into()
+                        .map_err(|_| wang_id.invalid("8 wang colors"))?,
+                ))
+            })
+            .collect::<Result<_, MapError>>()?,
+        properties: parse_properties(value)?,
+    })
+}
+
+fn parse_object(value: &Json) -> Result<Object, MapError> {
+    let points = |points: Json| {
+        points
+            .array()?
+            .iter()
+            .map(|point| Ok((point.get("x").f32()?, point.get("y").f32()?)))
+            .collect::<Result<Vec<_>, MapError>>()
+    };
+
+    let shape = if value.get("point").or(false, Json::bool)? {
+        Shape::Point
+    } else if value.get("ellipse").or(false, Json::bool)? {
+        Shape::Ellipse
+    } else if !value.get("polygon").is_null() {
+        Shape::Polygon(points(value.get("polygon"))?)
+    } else if !value.get("polyline").is_null() {
+        Shape::Polyline(points(value.get("polyline"))?)
+    } else {
+        Shape::Rectangle
+    };
+
+    // Tiled 1.9 renamed "type" to "class", JSON exports may use either
+    let class = match value.get("class").is_null() {
+        true => value.get("type").or("", Json::str)?,
+        false => value.get("class").str()?,
+    };
+
+    Ok(Object {
+        name: value.get("name").or("", Json::str)?.to_string(),
+        id: value.get("id").u32()?,
+        class: class.to_string(),
+        position: (value.get("x").f32()?, value.get("y").f32()?),
+        size: (
+            value.get("width").or(0.0, Json::f32)?,
+            value.get("height").or(0.0, Json::f32)?,
+        ),
+        rotation: value.get("rotation").or(0.0, Json::f32)?,
+        visible: value.get("visible").or(true, Json::bool)?,
+        shape,
+        properties: parse_properties(value)?,
+    })
+}
@@ -0,0 +1,757 @@
+use super::json::{load_json, Json};
+use super::{
+    load_image, resolve_path, Chunk, LayerAttributes, Layers, Map, MapError, Object, Orientation,
+    Properties, PropertyValue, RenderOrder, Shape, Tile, TileData, TileSet,
+};
+
+/// Size in cells of the chunks level layers are split into, the one Tiled uses for infinite maps.
+const CHUNK_SIZE: i32 = 16;
+
+/// Name of the hidden object group holding a rectangle per level.
+const LEVELS_LAYER: &str = "levels";
+
+/// Bool property marking the tile layer the game builds its colliders from, set
+/// on the hidden layers with the IntGrid values.
+const COLLISION_PROPERTY: &str = "collision";
+
+/// A level of the project and its top-left in the world, in pixels.
+struct Level<'a> {
+    value: Json<'a>,
+    position: (i32, i32),
+    layer_instances: Vec<Json<'a>>,
+}
+
+/// Cells of the tile layers built for a layer definition, split in sub-layers
+/// where tiles are stacked on the same cell.
+#[derive(Default)]
+struct TileLayers {
+    depths: Vec<std::collections::BTreeMap<(i32, i32), Chunk>>,
+    stacked: std::collections::HashMap<(i32, i32), usize>,
+}
+
+impl TileLayers {
+    /// Puts `tile` over the ones already at `cell`.
+    fn push(&mut self, cell: (i32, i32), tile: Tile) {
+        let depth = self.stacked.entry(cell).or_default();
+        if *depth == self.depths.len() {
+            self.depths.push(std::collections::BTreeMap::new());
+        }
+
+        let position = (
+            cell.0.div_euclid(CHUNK_SIZE) * CHUNK_SIZE,
+            cell.1.div_euclid(CHUNK_SIZE) * CHUNK_SIZE,
+        );
+        let chunk = self.depths[*depth]
+            .entry(position)
+            .or_insert_with(|| Chunk {
+                position,
+                size: (CHUNK_SIZE as u32, CHUNK_SIZE as u32),
+                data: vec![Tile::default(); (CHUNK_SIZE * CHUNK_SIZE) as usize],
+            });
+        chunk.data[((cell.0 - position.0) + (cell.1 - position.1) * CHUNK_SIZE) as usize] = tile;
+        *depth += 1;
+    }
+
+    fn into_layers(
+        self,
+        next_id: &mut u32,
+        name: &str,
+        visible: bool,
+        attributes: &LayerAttributes,
+        properties: &Properties,
+    ) -> Vec<Layers> {
+        self.depths
+            .into_iter()
+            .map(|chunks| {
+                *next_id += 1;
+                Layers::TileLayer {
+                    id: *next_id,
+                    name: name.to_string(),
+                    visible,
+                    attributes: attributes.clone(),
+                    properties: properties.clone(),
+                    data: vec![],
+                    chunks: chunks.into_values().collect(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Loads every level of an LDtk project into a single infinite map, laid out as
+/// in the world view of LDtk. Each layer definition becomes a tile layer holding
+/// the cells of all the levels, or an object group for entity layers. Levels are
+/// also added as rectangles to a hidden `levels` object group.
+///
+/// IntGrid values get a generated tileset with a tile of their color per value.
+/// Their hidden layers are marked as collision layers, so every cell with a value
+/// blocks the player; in projects with several IntGrid layers the game uses the
+/// bottom one.
+pub async fn load_map(path_data: &str) -> Result<Map, MapError> {
+    let value = load_json(path_data).await?;
+    let root = Json {
+        file: path_data,
+        path: "$".to_string(),
+        value: &value,
+    };
+    let defs = root.get("defs");
+    let grid_size = root.get("defaultGridSize").i32()?;
+
+    // Levels saved in separate files are loaded first so they can be borrowed below
+    let mut external = vec![];
+    for level in root.get("levels").array()? {
+        if level.get("layerInstances").is_null() {
+            let path = resolve_path(path_data, level.get("externalRelPath").str()?);
+            let value = load_json(&path).await?;
+            external.push((path, value));
+        }
+    }
+
+    let layout = root.get("worldLayout").or("Free", Json::str)?;
+    let mut external = external.iter();
+    let mut cursor = 0;
+    let mut levels = vec![];
+    for level in root.get("levels").array()? {
+        let layer_instances = match level.get("layerInstances").is_null() {
+            true => {
+                let (path, value) = external.next().unwrap();
+                Json {
+                    file: path,
+                    path: "$".to_string(),
+                    value,
+                }
+                .get("layerInstances")
+                .array()?
+            }
+            false => level.get("layerInstances").array()?,
+        };
+
+        // Linear layouts don't store the world position of their levels
+        let position = match layout {
+            "LinearHorizontal" => {
+                cursor += level.get("pxWid").i32()?;
+                (cursor - level.get("pxWid").i32()?, 0)
+            }
+            "LinearVertical" => {
+                cursor += level.get("pxHei").i32()?;
+                (0, cursor - level.get("pxHei").i32()?)
+            }
+            _ => (level.get("worldX").i32()?, level.get("worldY").i32()?),
+        };
+        levels.push(Level {
+            value: level,
+            position,
+            layer_instances,
+        });
+    }
+
+    let mut tilesets = vec![];
+    let mut tileset_gids = std::collections::HashMap::new();
+    let mut next_gid = 1;
+    for tileset in defs.get("tilesets").array()? {
+        // Embedded atlases such as the LDtk icons have no image to load
+        let rel_path = tileset.get("relPath").or("", Json::str)?;
+        if rel_path.is_empty() {
+            continue;
+        }
+        let image_path = resolve_path(path_data, rel_path);
+        let (image, image_size) = load_image(&image_path).await?;
+        let tile_size = tileset.get("tileGridSize").u32()?;
+        let (columns, rows) = (tileset.get("__cWid").u32()?, tileset.get("__cHei").u32()?);
+        tileset_gids.insert(tileset.get("uid").i64()?, next_gid);
+        tilesets.push(TileSet {
+            name: tileset.get("identifier").str()?.to_string(),
+            first_gid: next_gid,
+            image,
+            image_path,
+            columns,
+            tile_count: columns * rows,
+            tile_size: (tile_size, tile_size),
+            margin: tileset.get("padding").or(0, Json::u32)?,
+            spacing: tileset.get("spacing").or(0, Json::u32)?,
+            image_size,
+            properties: Properties::new(),
+            tiles: parse_tile_data(&tileset)?,
+            wang_sets: Vec::new(),
+        });
+        next_gid += columns * rows;
+    }
+
+    let mut int_grid_gids = std::collections::HashMap::new();
+    for layer in defs.get("layers").array()? {
+        let values = layer.get("intGridValues").or(vec![], Json::array)?;
+        if layer.get("type").str()? != "IntGrid" || values.is_empty() {
+            continue;
+        }
+
+        let uid = layer.get("uid").i64()?;
+        let mut tiles = std::collections::BTreeMap::new();
+        let mut colors = vec![];
+        for (i, value) in values.iter().enumerate() {
+            let mut properties = Properties::new();
+            properties.insert(
+                "value".to_string(),
+                PropertyValue::Int(value.get("value").i64()?),
+            );
+            if let Some(identifier) = value
+                .get("identifier")
+                .or(None, |json| json.str().map(Some))?
+            {
+                properties.insert(
+                    "identifier".to_string(),
+                    PropertyValue::String(identifier.to_string()),
+                );
+            }
+            tiles.insert(
+                i as u32,
+                TileData {
+                    properties,
+                    ..Default::default()
+                },
+            );
+            colors.push(value.get("color").color()?);
+            int_grid_gids.insert((uid, value.get("value").i64()?), next_gid + i as u32);
+        }
+
+        tilesets.push(TileSet {
+            name: layer.get("identifier").str()?.to_string(),
+            first_gid: next_gid,
+            image: int_grid_image(&colors, grid_size as u32),
+            image_path: String::new(),
+            columns: colors.len() as u32,
+            tile_count: colors.len() as u32,
+            tile_size: (grid_size as u32, grid_size as u32),
+            margin: 0,
+            spacing: 0,
+            image_size: (colors.len() as u32 * grid_size as u32, grid_size as u32),
+            properties: Properties::new(),
+            tiles,
+            wang_sets: Vec::new(),
+        });
+        next_gid += colors.len() as u32;
+    }
+
+    // Layer definitions are listed from the top one down
+    let mut next_id = 0;
+    let mut layers = vec![];
+    for layer in defs.get("layers").array()?.into_iter().rev() {
+        let uid = layer.get("uid").i64()?;
+        let name = layer.get("identifier").str()?;
+        let attributes = LayerAttributes {
+            opacity: layer.get("displayOpacity").or(1.0, Json::f32)?,
+            offset: (
+                layer.get("pxOffsetX").or(0.0, Json::f32)?,
+                layer.get("pxOffsetY").or(0.0, Json::f32)?,
+            ),
+            // LDtk factors are how much slower than the camera the layer scrolls
+            parallax: (
+                1.0 - layer.get("parallaxFactorX").or(0.0, Json::f32)?,
+                1.0 - layer.get("parallaxFactorY").or(0.0, Json::f32)?,
+            ),
+            ..Default::default()
+        };
+
+        let mut instances = vec![];
+        for level in &levels {
+            for instance in &level.layer_instances {
+                if instance.get("layerDefUid").i64()? == uid {
+                    if instance.get("__gridSize").i32()? != grid_size {
+                        return Err(instance
+                            .get("__gridSize")
+                            .invalid("the default grid size of the project"));
+                    }
+                    instances.push((level.position, instance));
+                }
+            }
+        }
+        let mut visible = false;
+        for (_, instance) in &instances {
+            visible |= instance.get("visible").or(true, Json::bool)?;
+        }
+
+        match layer.get("type").str()? {
+            "Entities" => {
+                let mut objects = vec![];
+                for (position, instance) in &instances {
+                    for entity in instance.get("entityInstances").array()? {
+                        next_id += 1;
+                        objects.push(parse_entity(&entity, *position, next_id)?);
+                    }
+                }
+                next_id += 1;
+                layers.push(Layers::ObjectGroup {
+                    id: next_id,
+                    name: name.to_string(),
+                    visible,
+                    attributes,
+                    properties: Properties::new(),
+                    objects,
+                });
+            }
+            layer_type => {
+                let tiles_key = match layer_type {
+                    "Tiles" => "gridTiles",
+                    _ => "autoLayerTiles",
+                };
+                let mut tile_layers = TileLayers::default();
+                for (position, instance) in &instances {
+                    let Some(&first_gid) =
+                        tileset_gids.get(&instance.get("__tilesetDefUid").or(-1, Json::i64)?)
+                    else {
+                        continue;
+                    };
+                    for tile in instance.get(tiles_key).or(vec![], Json::array)? {
+                        let (x, y) = tile.get("px").pair("an array of two integers")?;
+                        let flip = tile.get("f").or(0, Json::u32)?;
+                        tile_layers.push(
+                            level_cell(*position, x.i32()?, y.i32()?, grid_size),
+                            Tile {
+                                gid: first_gid + tile.get("t").u32()?,
+                                flip_horizontal: flip & 1 != 0,
+                                flip_vertical: flip & 2 != 0,
+                                ..Default::default()
+                            },
+                        );
+                    }
+                }
+
+                // IntGrid auto-layer tiles are drawn on top of their hidden values
+                let auto_tiles_name = match layer_type {
+                    "IntGrid" => format!("{} tiles", name),
+                    _ => name.to_string(),
+                };
+                let tile_layers = tile_layers.into_layers(
+                    &mut next_id,
+                    &auto_tiles_name,
+                    visible,
+                    &attributes,
+                    &Properties::new(),
+                );
+
+                if layer_type == "IntGrid" {
+                    let mut values = TileLayers::default();
+                    for (position, instance) in &instances {
+                        let width = instance.get("__cWid").i32()?;
+                        for (i, value) in instance.get("intGridCsv").array()?.iter().enumerate() {
+                            let Some(&gid) = int_grid_gids.get(&(uid, value.i64()?)) else {
+                                continue;
+                            };
+                            let (x, y) = (i as i32 % width, i as i32 / width);
+                            values.push(
+                                level_cell(*position, x * grid_size, y * grid_size, grid_size),
+                                Tile {
+                                    gid,
+                                    ..Default::default()
+                                },
+                            );
+                        }
+                    }
+                    layers.extend(values.into_layers(
+                        &mut next_id,
+                        name,
+                        false,
+                        &attributes,
+                        &Properties::from([(
+                            COLLISION_PROPERTY.to_string(),
+                            PropertyValue::Bool(true),
+                        )]),
+                    ));
+                }
+                layers.extend(tile_layers);
+            }
+        }
+    }
+
+    let mut level_objects = vec![];
+    for level in &levels {
+        next_id += 1;
+        level_objects.push(Object {
+            name: level.value.get("identifier").str()?.to_string(),
+            id: next_id,
+            class: "level".to_string(),
+            position: (level.position.0 as f32, level.position.1 as f32),
+            size: (
+                level.value.get("pxWid").f32()?,
+                level.value.get("pxHei").f32()?,
+            ),
+            rotation: 0.0,
+            visible: true,
+            shape: Shape::Rectangle,
+            properties: parse_fields(&level.value)?,
+        });
+    }
+    next_id += 1;
+    layers.push(Layers::ObjectGroup {
+        id: next_id,
+        name: LEVELS_LAYER.to_string(),
+        visible: false,
+        attributes: LayerAttributes::default(),
+        properties: Properties::new(),
+        objects: level_objects,
+    });
+
+    let mut size = (0, 0);
+    for level in &levels {
+        let right = level.position.0 + level.value.get("pxWid").i32()?;
+        let bottom = level.position.1 + level.value.get("pxHei").i32()?;
+        size.0 = size.0.max((right as f32 / grid_size as f32).ceil() as u32);
+        size.1 = size.1.max((bottom as f32 / grid_size as f32).ceil() as u32);
+    }
+
+    Ok(Map {
+        size,
+        infinite: true,
+        orientation: Orientation::Orthogonal,
+        render_order: RenderOrder::RightDown,
+        tile_size: (grid_size as u32, grid_size as u32),
+        parallax_origin: (0.0, 0.0),
+        properties: Properties::new(),
+        layers,
+        tilesets,
+    })
+}
+
+/// World cell of the pixel (x, y) of a level at `position`. Levels that aren't
+/// aligned to the grid are snapped to it.
+fn level_cell(position: (i32, i32), x: i32, y: i32, grid_size: i32) -> (i32, i32) {
+    (
+        position.0.div_euclid(grid_size) + x.div_euclid(grid_size),
+        position.1.div_euclid(grid_size) + y.div_euclid(grid_size),
+    )
+}
+
+/// Custom data of the tiles of a tileset as a `data` string property, and
+/// the enum values tiles are tagged with as bool properties.
+fn parse_tile_data(tileset: &Json) -> Result<std::collections::BTreeMap<u32, TileData>, MapError> {
+    let mut tiles = std::collections::BTreeMap::<u32, TileData>::new();
+    for custom in tileset.get("customData").or(vec![], Json::array)? {
+        tiles
+            .entry(custom.get("tileId").u32()?)
+            .or_default()
+            .properties
+            .insert(
+                "data".to_string(),
+                PropertyValue::String(custom.get("data").str()?.to_string()),
+            );
+    }
+    for tag in tileset.get("enumTags").or(vec![], Json::array)? {
+        let name = tag.get("enumValueId").str()?;
+        for tile_id in tag.get("tileIds").array()? {
+            tiles
+                .entry(tile_id.u32()?)
+                .or_default()
+                .properties
+                .insert(name.to_string(), PropertyValue::Bool(true));
+        }
+    }
+    Ok(tiles)
+}
+
+/// PNG with a tile of each color in a row.
+fn int_grid_image(colors: &[[f32; 4]], tile_size: u32) -> Vec<u8> {
+    let image = image::RgbaImage::from_fn(colors.len() as u32 * tile_size, tile_size, |x, _| {
+        image::Rgba(colors[(x / tile_size) as usize].map(|channel| (channel * 255.0) as u8))
+    });
+    let mut bytes = std::io::Cursor::new(vec![]);
+    image::DynamicImage::ImageRgba8(image)
+        .write_to(&mut bytes, image::ImageOutputFormat::Png)
+        .unwrap();
+    bytes.into_inner()
+}
+
+/// An entity as a rectangle object, named after its definition.
+fn parse_entity(entity: &Json, level: (i32, i32), id: u32) -> Result<Object, MapError> {
+    let identifier = entity.get("__identifier").str()?;
+    let px = entity.get("px").pair("an array of two numbers")?;
+    let pivot = entity.get("__pivot").pair("an array of two numbers")?;
+    let size = (entity.get("width").f32()?, entity.get("height").f32()?);
+
+    // `px` is where the pivot of the entity is, Tiled objects start at their top-left
+    Ok(Object {
+        name: identifier.to_string(),
+        id,
+        class: identifier.to_string(),
+        position: (
+            level.0 as f32 + px.0.f32()? - pivot.0.f32()? * size.0,
+            level.1 as f32 + px.1.f32()? - pivot.1.f32()? * size.1,
+        ),
+        size,
+        rotation: 0.0,
+        visible: true,
+        shape: Shape::Rectangle,
+        properties: parse_fields(entity)?,
+    })
+}
+
+/// The `fieldInstances` of a level or entity. Unset fields and tile references are skipped.
+fn parse_fields(value: &Json) -> Result<Properties, MapError> {
+    let mut properties = Properties::new();
+    for field in value.get("fieldInstances").or(vec![], Json::array)? {
+        if let Some(value) = parse_field(field.get("__type").str()?, &field.get("__value"))? {
+            properties.insert(field.get("__identifier").str()?.to_string(), value);
+        }
+    }
+    Ok(properties)
+}
+
+fn parse_field(field_type: &str, value: &Json) -> Result<Option<PropertyValue>, MapError> {
+    if value.is_null() {
+        return Ok(None);
+    }
+
+    // Arrays become a class whose members are the items by index
+    if let Some(item_type) = field_type
+        .strip_prefix("Array<")
+        .and_then(|item_type| item_type.strip_suffix('>'))
+    {
+        let mut properties = Properties::new();
+        for (i, item) in value.array()?.iter().enumerate() {
+            if let Some(item) = parse_field(item_type, item)? {
+                properties.insert(i.to_string(), item);
+            }
+        }
+        return Ok(Some(PropertyValue::Class {
+            class: field_type.to_string(),
+            properties,
+        }));
+    }
+
+    Ok(Some(match field_type {
+        "Int" => PropertyValue::Int(value.i64()?),
+        "Float" => PropertyValue::Float(value.f64()?),
+        "Bool" => PropertyValue::Bool(value.bool()?),
+        "String" | "Multilines" => PropertyValue::String(value.str()?.to_string()),
+        "Color" => PropertyValue::Color(Some(value.color()?)),
+        "FilePath" => PropertyValue::File(value.str()?.to_string()),
+        "EntityRef" => PropertyValue::String(value.get("entityIid").str()?.to_string()),
+        "Point" => PropertyValue::Class {
+            class: "Point".to_string(),
+            properties: Properties::from([
+                ("cx".to_string(), PropertyValue::Int(value.get("cx").i64()?)),
+                ("cy".to_string(), PropertyValue::Int(value.get("cy").i64()?)),
+            ]),
+        },
+        _ if field_type.starts_with("LocalEnum.") || field_type.starts_with("ExternEnum.") => {
+            PropertyValue::String(value.str()?.to_string())
+        }
+        _ => return Ok(None),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    const PROJECT: &str = "./resources/ldtk/world.ldtk";
+    /// Where the tests write the projects they build.
+    const SAVED: &str = "./target/ldtk-tests";
+
+    fn load(path: &str) -> super::Map {
+        pollster::block_on(super::super::load_map(path)).unwrap()
+    }
+
+    fn tile(gid: u32, flip_horizontal: bool, flip_vertical: bool) -> super::Tile {
+        super::Tile {
+            gid,
+            flip_horizontal,
+            flip_vertical,
+            ..Default::default()
+        }
+    }
+
+    /// A project of two 2x1 levels laid out by `layout`, with an IntGrid value on
+    /// the first cell of each.
+    fn linear(layout: &str) -> super::Map {
+        let level = |identifier: &str| {
+            serde_json::json!({
+                "identifier": identifier,
+                "worldX": -1,
+                "worldY": -1,
+                "pxWid": 32,
+                "pxHei": 16,
+                "layerInstances": [{
+                    "__gridSize": 16,
+                    "__cWid": 2,
+                    "layerDefUid": 1,
+                    "intGridCsv": [1, 0],
+                }],
+            })
+        };
+        let project = serde_json::json!({
+            "defaultGridSize": 16,
+            "worldLayout": layout,
+            "defs": {
+                "tilesets": [],
+                "layers": [{
+                    "identifier": "Walls",
+                    "type": "IntGrid",
+                    "uid": 1,
+                    "intGridValues": [{ "value": 1, "color": "#000000" }],
+                }],
+            },
+            "levels": [level("First"), level("Second")],
+        });
+
+        std::fs::create_dir_all(SAVED).unwrap();
+        let path = format!("{}/{}.ldtk", SAVED, layout);
+        std::fs::write(&path, project.to_string()).unwrap();
+        load(&path)
+    }
+
+    #[test]
+    fn loads_layers_bottom_up() {
+        let map = load(PROJECT);
+        let layers = map
+            .all_layers()
+            .iter()
+            .map(|layer| (layer.name().to_string(), layer.visible()))
+            .collect::<Vec<_>>();
+        let layer = |name: &str, visible: bool| (name.to_string(), visible);
+        assert_eq!(
+            layers,
+            [
+                layer("Walls", false),
+                layer("Walls tiles", true),
+                layer("Decor", true),
+                layer("Decor", true),
+                layer("Entities", true),
+                layer("levels", false),
+            ]
+        );
+        assert!(map.infinite);
+        assert_eq!(map.size, (6, 3));
+        assert_eq!(map.all_layers()[1].attributes().opacity, 0.5);
+    }
+
+    #[test]
+    fn loads_tilesets_with_images() {
+        let map = load(PROJECT);
+        let names = map
+            .tilesets
+            .iter()
+            .map(|tileset| (tileset.name.as_str(), tileset.first_gid))
+            .collect::<Vec<_>>();
+        assert_eq!(names, [("Sunnyside", 1), ("Walls", 4097)]);
+
+        let sunnyside = &map.tilesets[0];
+        assert_eq!(sunnyside.image_size, (1024, 1024));
+        assert_eq!(
+            sunnyside.tiles[&5].properties["data"],
+            super::PropertyValue::String("chest".to_string())
+        );
+        assert_eq!(
+            sunnyside.tiles[&7].properties["Solid"],
+            super::PropertyValue::Bool(true)
+        );
+
+        let walls = &map.tilesets[1];
+        assert_eq!(walls.image_size, (32, 16));
+        assert_eq!(
+            walls.tiles[&0].properties,
+            super::Properties::from([
+                (
+                    "identifier".to_string(),
+                    super::PropertyValue::String("wall".to_string())
+                ),
+                ("value".to_string(), super::PropertyValue::Int(1)),
+            ])
+        );
+        assert_eq!(
+            walls.tiles[&1].properties,
+            super::Properties::from([("value".to_string(), super::PropertyValue::Int(2))])
+        );
+    }
+
+    #[test]
+    fn int_grid_values_are_a_collision_layer() {
+        let map = load(PROJECT);
+        let walls = map.find_layer("Walls").unwrap();
+        assert_eq!(
+            map.layer(walls).properties().get("collision"),
+            Some(&super::PropertyValue::Bool(true))
+        );
+
+        // Values of the external level are offset by its position
+        let cells = [(0, 0), (1, 0), (3, 0), (3, 1), (4, 1), (5, 1)];
+        assert_eq!(
+            cells.map(|(x, y)| map.tile(walls, x, y).gid),
+            [4097, 0, 4098, 4097, 0, 4097]
+        );
+    }
+
+    #[test]
+    fn loads_auto_and_grid_tiles_with_flips() {
+        let map = load(PROJECT);
+        assert_eq!(map.tile(1, 3, 0), tile(11, false, true));
+        assert_eq!(map.tile(2, 1, 1), tile(8, true, true));
+    }
+
+    #[test]
+    fn stacks_tiles_of_a_cell_in_sub_layers() {
+        let map = load(PROJECT);
+        assert_eq!(map.tile(2, 0, 0), tile(6, true, false));
+        assert_eq!(map.tile(3, 0, 0), tile(7, false, false));
+        assert_eq!(map.tile(3, 1, 1), tile(0, false, false));
+    }
+
+    #[test]
+    fn loads_entities_and_levels_as_objects() {
+        let map = load(PROJECT);
+        let player = map.find_object("Player").unwrap();
+        assert_eq!(player.class, "Player");
+        assert_eq!((player.position, player.size), ((0.0, 0.0), (16.0, 16.0)));
+        assert_eq!(player.properties["lives"], super::PropertyValue::Int(3));
+
+        let start = map.find_object("Start").unwrap();
+        assert_eq!(start.class, "level");
+        assert_eq!(
+            start.properties["music"],
+            super::PropertyValue::String("calm".to_string())
+        );
+        let cave = map.find_object("Cave").unwrap();
+        assert_eq!((cave.position, cave.size), ((64.0, 16.0), (32.0, 32.0)));
+    }
+
+    #[test]
+    fn lays_linear_levels_side_by_side() {
+        let map = linear("LinearHorizontal");
+        assert_eq!(map.size, (4, 1));
+        assert_eq!(map.tile(0, 2, 0).gid, 1);
+        assert_eq!(map.find_object("Second").unwrap().position, (32.0, 0.0));
+
+        let map = linear("LinearVertical");
+        assert_eq!(map.size, (2, 2));
+        assert_eq!(map.tile(0, 0, 1).gid, 1);
+        assert_eq!(map.find_object("Second").unwrap().position, (0.0, 16.0));
+    }
+
+    fn entity(px: serde_json::Value) -> Result<super::Object, super::MapError> {
+        let value = serde_json::json!({
+            "__identifier": "Player",
+            "px": px,
+            "__pivot": [0.5, 1.0],
+            "width": 16,
+            "height": 32,
+        });
+        let json = super::Json {
+            file: "world.ldtk",
+            path: "entityInstances[0]".to_string(),
+            value: &value,
+        };
+        super::parse_entity(&json, (100, 200), 1)
+    }
+
+    #[test]
+    fn entities_start_at_their_top_left() {
+        let object = entity(serde_json::json!([40, 64])).unwrap();
+        assert_eq!(object.position, (132.0, 232.0));
+        assert_eq!(object.size, (16.0, 32.0));
+    }
+
+    #[test]
+    fn short_positions_are_invalid() {
+        let error = entity(serde_json::json!([40])).unwrap_err();
+        assert!(matches!(
+            error,
+            super::MapError::InvalidField { ref field, .. } if field == "entityInstances[0].px"
+        ));
+    }
+}
@@ -0,0 +1,486 @@
+use super::Map;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StaggerAxis {
+    X,
+    Y,
+}
+
+/// Whether the odd or the even rows (or columns) are shifted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StaggerIndex {
+    Odd,
+    Even,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    Orthogonal,
+    Isometric,
+    /// Isometric tiles laid in shifted rows or columns, a hexagonal map without side length.
+    Staggered {
+        axis: StaggerAxis,
+        index: StaggerIndex,
+    },
+    Hexagonal {
+        /// Length in pixels of the flat sides of the hexagons.
+        side_length: u32,
+        axis: StaggerAxis,
+        index: StaggerIndex,
+    },
+}
+
+/// Order Tiled draws the tiles of orthogonal maps in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderOrder {
+    RightDown,
+    RightUp,
+    LeftDown,
+    LeftUp,
+}
+
+impl Orientation {
+    /// Parses the `orientation`, `staggeraxis`, `staggerindex` and `hexsidelength` map fields.
+    pub fn parse(
+        orientation: &str,
+        axis: &str,
+        index: &str,
+        side_length: u32,
+    ) -> Result<Self, super::MapError> {
+        let axis = match axis {
+            "x" => StaggerAxis::X,
+            _ => StaggerAxis::Y,
+        };
+        let index = match index {
+            "even" => StaggerIndex::Even,
+            _ => StaggerIndex::Odd,
+        };
+
+        match orientation {
+            "orthogonal" => Ok(Orientation::Orthogonal),
+            "isometric" => Ok(Orientation::Isometric),
+            "staggered" => Ok(Orientation::Staggered { axis, index }),
+            "hexagonal" => Ok(Orientation::Hexagonal {
+                side_length,
+                axis,
+                index,
+            }),
+            orientation => Err(super::MapError::UnsupportedOrientation(
+                orientation.to_string(),
+            )),
+        }
+    }
+
+    /// The `orientation` map field.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Orientation::Orthogonal => "orthogonal",
+            Orientation::Isometric => "isometric",
+            Orientation::Staggered { .. } => "staggered",
+            Orientation::Hexagonal { .. } => "hexagonal",
+        }
+    }
+
+    /// The `staggeraxis`, `staggerindex` and `hexsidelength` map fields,
+    /// `None` for maps that aren't staggered.
+    pub fn stagger_fields(&self) -> Option<(&'static str, &'static str, u32)> {
+        let (axis, index, side_length) = match *self {
+            Orientation::Staggered { axis, index } => (axis, index, 0),
+            Orientation::Hexagonal {
+                side_length,
+                axis,
+                index,
+            } => (axis, index, side_length),
+            _ => return None,
+        };
+        let axis = match axis {
+            StaggerAxis::X => "x",
+            StaggerAxis::Y => "y",
+        };
+        let index = match index {
+            StaggerIndex::Odd => "odd",
+            StaggerIndex::Even => "even",
+        };
+        Some((axis, index, side_length))
+    }
+}
+
+impl RenderOrder {
+    pub fn parse(render_order: &str) -> Self {
+        match render_order {
+            "right-up" => RenderOrder::RightUp,
+            "left-down" => RenderOrder::LeftDown,
+            "left-up" => RenderOrder::LeftUp,
+            _ => RenderOrder::RightDown,
+        }
+    }
+
+    /// The `renderorder` map field.
+    pub fn name(&self) -> &'static str {
+        match self {
+            RenderOrder::RightDown => "right-down",
+            RenderOrder::RightUp => "right-up",
+            RenderOrder::LeftDown => "left-down",
+            RenderOrder::LeftUp => "left-up",
+        }
+    }
+}
+
+/// Layout of the rows or columns of staggered and hexagonal maps, in pixels.
+struct Stagger {
+    axis: StaggerAxis,
+    index: StaggerIndex,
+    side_offset: (f32, f32),
+    column_width: f32,
+    row_height: f32,
+}
+
+impl Stagger {
+    fn is_staggered(&self, x: i32, y: i32) -> bool {
+        let line = match self.axis {
+            StaggerAxis::X => x,
+            StaggerAxis::Y => y,
+        };
+        (line.rem_euclid(2) == 1) == (self.index == StaggerIndex::Odd)
+    }
+}
+
+impl Map {
+    fn tile_size_f32(&self) -> (f32, f32) {
+        (self.tile_size.0 as f32, self.tile_size.1 as f32)
+    }
+
+    fn stagger(&self) -> Option<Stagger> {
+        let (axis, index, side_length) = match self.orientation {
+            Orientation::Staggered { axis, index } => (axis, index, 0.0),
+            Orientation::Hexagonal {
+                side_length,
+                axis,
+                index,
+            } => (axis, index, side_length as f32),
+            _ => return None,
+        };
+
+        let (tile_width, tile_height) = self.tile_size_f32();
+        Some(match axis {
+            StaggerAxis::X => {
+                let side_offset = (tile_width - side_length) / 2.0;
+                Stagger {
+                    axis,
+                    index,
+                    side_offset: (side_offset, 0.0),
+                    column_width: side_offset + side_length,
+                    row_height: tile_height / 2.0,
+                }
+            }
+            StaggerAxis::Y => {
+                let side_offset = (tile_height - side_length) / 2.0;
+                Stagger {
+                    axis,
+                    index,
+                    side_offset: (0.0, side_offset),
+                    column_width: tile_width / 2.0,
+                    row_height: side_offset + side_length,
+                }
+            }
+        })
+    }
+
+    /// Size of the map in Tiled pixels, as Tiled renders it.
+    pub fn pixel_size(&self) -> (f32, f32) {
+        let (tile_width, tile_height) = self.tile_size_f32();
+        let (width, height) = (self.size.0 as f32, self.size.1 as f32);
+
+        match (self.orientation, self.stagger()) {
+            (Orientation::Isometric, _) => (
+                (width + height) * tile_width / 2.0,
+                (width + height) * tile_height / 2.0,
+            ),
+            (_, Some(stagger)) if stagger.axis == StaggerAxis::X => (
+                width * stagger.column_width + stagger.side_offset.0,
+                height * tile_height + if width > 1.0 { stagger.row_height } else { 0.0 },
+            ),
+            (_, Some(stagger)) => (
+                width * tile_width
+                    + if height > 1.0 {
+                        stagger.column_width
+                    } else {
+                        0.0
+                    },
+                height * stagger.row_height + stagger.side_offset.1,
+            ),
+            _ => (width * tile_width, height * tile_height),
+        }
+    }
+
+    /// Center of cell (x, y) in Tiled pixels.
+    pub fn cell_center(&self, x: i32, y: i32) -> (f32, f32) {
+        let (tile_width, tile_height) = self.tile_size_f32();
+        let (x_f32, y_f32) = (x as f32, y as f32);
+
+        let top_left = match (self.orientation, self.stagger()) {
+            (Orientation::Isometric, _) => (
+                (x_f32 - y_f32) * tile_width / 2.0 + self.size.1 as f32 * tile_width / 2.0
+                    - tile_width / 2.0,
+                (x_f32 + y_f32) * tile_height / 2.0,
+            ),
+            (_, Some(stagger)) if stagger.axis == StaggerAxis::X => (
+                x_f32 * stagger.column_width,
+                y_f32 * tile_height
+                    + if stagger.is_staggered(x, y) {
+                        stagger.row_height
+                    } else {
+                        0.0
+                    },
+            ),
+            (_, Some(stagger)) => (
+                x_f32 * tile_width
+                    + if stagger.is_staggered(x, y) {
+                        stagger.column_width
+                    } else {
+                        0.0
+                    },
+                y_f32 * stagger.row_height,
+            ),
+            _ => (x_f32 * tile_width, y_f32 * tile_height),
+        };
+
+        (
+            top_left.0 + tile_width / 2.0,
+            top_left.1 + tile_height / 2.0,
+        )
+    }
+
+    /// World position of the center of cell (x, y), see `to_world`.
+    pub fn cell_to_world(&self, x: i32, y: i32) -> nalgebra_glm::Vec2 {
+        self.to_world(self.cell_center(x, y))
+    }
+
+    /// Converts Tiled pixel coordinates (origin top-left, y down) into the
+    /// world coordinates used by `TileRenderer` (tiles scaled x2, y up,
+    /// tile centers on the grid).
+    pub fn to_world(&self, position: (f32, f32)) -> nalgebra_glm::Vec2 {
+        let (tile_width, tile_height) = self.tile_size_f32();
+        nalgebra_glm::vec2(
+            position.0 * 2.0 - tile_width,
+            (self.pixel_size().1 - position.1) * 2.0 - tile_height,
+        )
+    }
+
+    /// Inverse of `to_world`.
+    pub fn to_pixels(&self, position: nalgebra_glm::Vec2) -> (f32, f32) {
+        let (tile_width, tile_height) = self.tile_size_f32();
+        (
+            (position.x + tile_width) / 2.0,
+            self.pixel_size().1 - (position.y + tile_height) / 2.0,
+        )
+    }
+
+    /// World position of an object position. Objects of isometric maps are
+    /// stored in a projected space where both axes are measured in tile heights.
+    pub fn object_to_world(&self, position: (f32, f32)) -> nalgebra_glm::Vec2 {
+        match self.orientation {
+            Orientation::Isometric => {
+                let (tile_width, tile_height) = self.tile_size_f32();
+                let (x, y) = (position.0 / tile_height, position.1 / tile_height);
+                self.to_world((
+                    (x - y) * tile_width / 2.0 + self.size.1 as f32 * tile_width / 2.0,
+                    (x + y) * tile_height / 2.0,
+                ))
+            }
+            _ => self.to_world(position),
+        }
+    }
+
+    /// Outline of a cell in world units, relative to its center.
+    pub fn cell_outline(&self) -> Vec<nalgebra_glm::Vec2> {
+        let (tile_width, tile_height) = self.tile_size_f32();
+        let (half_width, half_height) = (tile_width, tile_height);
+
+        match (self.orientation, self.stagger()) {
+            (Orientation::Isometric, _) => vec![
+                nalgebra_glm::vec2(0.0, half_height),
+                nalgebra_glm::vec2(-half_width, 0.0),
+                nalgebra_glm::vec2(0.0, -half_height),
+                nalgebra_glm::vec2(half_width, 0.0),
+            ],
+            // Side offsets are in pixels, the outline is in world units (x2)
+            (_, Some(stagger)) if stagger.axis == StaggerAxis::X => {
+                let side = half_width - stagger.side_offset.0 * 2.0;
+                vec![
+                    nalgebra_glm::vec2(-side, half_height),
+                    nalgebra_glm::vec2(-half_width, 0.0),
+                    nalgebra_glm::vec2(-side, -half_height),
+                    nalgebra_glm::vec2(side, -half_height),
+                    nalgebra_glm::vec2(half_width, 0.0),
+                    nalgebra_glm::vec2(side, half_height),
+                ]
+            }
+            (_, Some(stagger)) => {
+                let side = half_height - stagger.side_offset.1 * 2.0;
+                vec![
+                    nalgebra_glm::vec2(0.0, half_height),
+                    nalgebra_glm::vec2(-half_width, side),
+                    nalgebra_glm::vec2(-half_width, -side),
+                    nalgebra_glm::vec2(0.0, -half_height),
+                    nalgebra_glm::vec2(half_width, -side),
+                    nalgebra_glm::vec2(half_width, side),
+                ]
+            }
+            _ => vec![
+                nalgebra_glm::vec2(-half_width, half_height),
+                nalgebra_glm::vec2(-half_width, -half_height),
+                nalgebra_glm::vec2(half_width, -half_height),
+                nalgebra_glm::vec2(half_width, half_height),
+            ],
+        }
+    }
+
+    /// Cell under a world position, used for mouse picking.
+    pub fn world_to_cell(&self, position: nalgebra_glm::Vec2) -> (i32, i32) {
+        let (tile_width, tile_height) = self.tile_size_f32();
+        let (x, y) = self.to_pixels(position);
+
+        let estimate = match (self.orientation, self.stagger()) {
+            (Orientation::Isometric, _) => {
+                let x = (x - self.size.1 as f32 * tile_width / 2.0) / tile_width;
+                let y = y / tile_height;
+                return ((y + x).floor() as i32, (y - x).floor() as i32);
+            }
+            (_, Some(stagger)) if stagger.axis == StaggerAxis::X => (
+                (x / stagger.column_width).floor(),
+                (y / tile_height).floor(),
+            ),
+            (_, Some(stagger)) => ((x / tile_width).floor(), (y / stagger.row_height).floor()),
+            _ => {
+                return (
+                    (x / tile_width).floor() as i32,
+                    (y / tile_height).floor() as i32,
+                )
+            }
+        };
+        let estimate = (estimate.0 as i32, estimate.1 as i32);
+
+        // Shifted rows and columns overlap, the cell is the neighbour whose outline holds the position
+        let outline = self.cell_outline();
+        (-1..=1)
+            .flat_map(|dy| (-1..=1).map(move |dx| (estimate.0 + dx, estimate.1 + dy)))
+            .find(|&(x, y)| {
+                let local = position - self.cell_to_world(x, y);
+                outline.iter().enumerate().all(|(i, a)| {
+                    let b = outline[(i + 1) % outline.len()];
+                    (b - a).perp(&(local - a)) >= 0.0
+                })
+            })
+            .unwrap_or(estimate)
+    }
+
+    /// Cell of the map under a world position, `None` past the bounds of a finite map.
+    pub fn pick_cell(&self, position: nalgebra_glm::Vec2) -> Option<(i32, i32)> {
+        let (x, y) = self.world_to_cell(position);
+        let inside = (0..self.size.0 as i32).contains(&x) && (0..self.size.1 as i32).contains(&y);
+        (self.infinite || inside).then_some((x, y))
+    }
+
+    /// Key sorting cells in the order they are drawn, so tiles in front cover the ones behind.
+    pub fn draw_key(&self, x: i32, y: i32) -> (i32, i32) {
+        match (self.orientation, self.stagger()) {
+            (Orientation::Orthogonal, _) => match self.render_order {
+                RenderOrder::RightDown => (y, x),
+                RenderOrder::RightUp => (-y, x),
+                RenderOrder::LeftDown => (y, -x),
+                RenderOrder::LeftUp => (-y, -x),
+            },
+            (Orientation::Isometric, _) => (x + y, x),
+            // Shifted columns are half a row lower than the others
+            (_, Some(stagger)) if stagger.axis == StaggerAxis::X => {
+                (y * 2 + stagger.is_staggered(x, y) as i32, x)
+            }
+            _ => (y, x),
+        }
+    }
+
+    /// Offset in world units from the cell center to the center of a tile of
+    /// `tile_size` pixels. Tiled anchors tiles bigger than the grid to the
+    /// bottom-left of their cell, and to the bottom-center on isometric maps.
+    pub fn tile_anchor(&self, tile_size: (u32, u32)) -> nalgebra_glm::Vec2 {
+        let x = match self.orientation {
+            Orientation::Isometric => 0.0,
+            _ => tile_size.0 as f32 - self.tile_size.0 as f32,
+        };
+        nalgebra_glm::vec2(x, tile_size.1 as f32 - self.tile_size.1 as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Orientation, StaggerAxis, StaggerIndex};
+
+    fn load(orientation: Orientation) -> super::Map {
+        let mut map = pollster::block_on(super::super::load_map("./resources/mapa.json")).unwrap();
+        map.orientation = orientation;
+        map
+    }
+
+    #[test]
+    fn picks_the_cell_under_every_part_of_it() {
+        let orientations = [
+            Orientation::Orthogonal,
+            Orientation::Isometric,
+            Orientation::Staggered {
+                axis: StaggerAxis::Y,
+                index: StaggerIndex::Odd,
+            },
+            Orientation::Staggered {
+                axis: StaggerAxis::X,
+                index: StaggerIndex::Even,
+            },
+            Orientation::Hexagonal {
+                side_length: 8,
+                axis: StaggerAxis::Y,
+                index: StaggerIndex::Even,
+            },
+            Orientation::Hexagonal {
+                side_length: 8,
+                axis: StaggerAxis::X,
+                index: StaggerIndex::Odd,
+            },
+        ];
+        for orientation in orientations {
+            let map = load(orientation);
+            let outline = map.cell_outline();
+            for (x, y) in
+                (0..map.size.1 as i32).flat_map(|y| (0..map.size.0 as i32).map(move |x| (x, y)))
+            {
+                let center = map.cell_to_world(x, y);
+                // The center and close to each corner, inside the cell
+                for position in std::iter::once(center)
+                    .chain(outline.iter().map(|corner| center + corner * 0.9))
+                {
+                    assert_eq!(
+                        map.pick_cell(position),
+                        Some((x, y)),
+                        "{:?} at {:?}",
+                        orientation,
+                        position
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn picks_no_cell_past_finite_maps() {
+        let mut map = load(Orientation::Isometric);
+        let (width, height) = (map.size.0 as i32, map.size.1 as i32);
+        for (x, y) in [(-1, 0), (0, -1), (width, 0), (0, height), (width, height)] {
+            assert_eq!(map.pick_cell(map.cell_to_world(x, y)), None);
+        }
+
+        map.infinite = true;
+        assert_eq!(
+            map.pick_cell(map.cell_to_world(-1, height)),
+            Some((-1, height))
+        );
+    }
+}
@@ -0,0 +1,479 @@
+use super::{
+    decode_tile_data, load_bytes, load_image, parse_color, resolve_path, Chunk, Frame,
+    LayerAttributes, Layers, Map, MapError, Object, Orientation, Properties, PropertyValue,
+    RenderOrder, Shape, Tile, TileData, TileSet, WangColor, WangSet, WangSetType,
+};
+
+const COLOR: &str = "a #RRGGBB or #AARRGGBB color";
+
+/// An XML element along with the file it comes from, so errors can point at it.
+#[derive(Clone, Copy)]
+struct Element<'a, 'input> {
+    file: &'a str,
+    node: roxmltree::Node<'a, 'input>,
+}
+
+impl<'a, 'input> Element<'a, 'input> {
+    /// Element, attribute and line of the node, used as the field of errors.
+    fn field(&self, name: &str) -> String {
+        let position = self.node.document().text_pos_at(self.node.range().start);
+        format!(
+            "<{}> {} (line {})",
+            self.node.tag_name().name(),
+            name,
+            position.row
+        )
+    }
+
+    fn invalid(&self, name: &str, expected: &'static str) -> MapError {
+        MapError::InvalidField {
+            path: self.file.to_string(),
+            field: self.field(name),
+            expected,
+        }
+    }
+
+    fn parse<T: std::str::FromStr>(&self, name: &str, value: &str) -> Result<T, MapError> {
+        value
+            .trim()
+            .parse::<T>()
+            .map_err(|_| self.invalid(name, std::any::type_name::<T>()))
+    }
+
+    fn str(&self, name: &str) -> Result<&'a str, MapError> {
+        self.node
+            .attribute(name)
+            .ok_or_else(|| MapError::MissingField {
+                path: self.file.to_string(),
+                field: self.field(name),
+            })
+    }
+
+    fn attribute<T: std::str::FromStr>(&self, name: &str) -> Result<T, MapError> {
+        self.parse(name, self.str(name)?)
+    }
+
+    fn attribute_or<T: std::str::FromStr>(&self, name: &str, default: T) -> Result<T, MapError> {
+        match self.node.attribute(name) {
+            Some(value) => self.parse(name, value),
+            None => Ok(default),
+        }
+    }
+
+    fn children(&self, name: &'a str) -> impl Iterator<Item = Element<'a, 'input>> + 'a {
+        let file = self.file;
+        self.node
+            .children()
+            .filter(move |child| child.has_tag_name(name))
+            .map(move |node| Element { file, node })
+    }
+
+    fn child(&self, name: &'a str) -> Option<Element<'a, 'input>> {
+        self.children(name).next()
+    }
+
+    fn required_child(&self, name: &'a str) -> Result<Element<'a, 'input>, MapError> {
+        self.child(name).ok_or_else(|| MapError::MissingField {
+            path: self.file.to_string(),
+            field: self.field(&format!("<{}>", name)),
+        })
+    }
+
+    fn text(&self) -> &'a str {
+        self.node.text().unwrap_or_default()
+    }
+}
+
+async fn load_xml(path: &str) -> Result<String, MapError> {
+    String::from_utf8(load_bytes(path).await?).map_err(|error| MapError::Io {
+        path: path.to_string(),
+        message: error.to_string(),
+    })
+}
+
+fn parse_xml<'input>(
+    path: &str,
+    text: &'input str,
+) -> Result<roxmltree::Document<'input>, MapError> {
+    roxmltree::Document::parse(text).map_err(|error| MapError::Xml {
+        path: path.to_string(),
+        error,
+    })
+}
+
+pub async fn load_map(path_data: &str) -> Result<Map, MapError> {
+    let text = load_xml(path_data).await?;
+    let document = parse_xml(path_data, &text)?;
+    let root = Element {
+        file: path_data,
+        node: document.root_element(),
+    };
+
+    let orientation = Orientation::parse(
+        root.node.attribute("orientation").unwrap_or("orthogonal"),
+        root.node.attribute("staggeraxis").unwrap_or("y"),
+        root.node.attribute("staggerindex").unwrap_or("odd"),
+        root.attribute_or("hexsidelength", 0)?,
+    )?;
+
+    let mut tilesets = vec![];
+    for element in root.children("tileset") {
+        let first_gid = element.attribute("firstgid")?;
+        let tileset = match element.node.attribute("source") {
+            Some(source) => load_tileset(&resolve_path(path_data, source), first_gid).await?,
+            None => parse_tileset(element, first_gid).await?,
+        };
+        tilesets.push(tileset);
+    }
+    tilesets.sort_by_key(|tileset| tileset.first_gid);
+
+    let layers = parse_layers(root).await?;
+
+    Ok(Map {
+        size: (root.attribute("width")?, root.attribute("height")?),
+        infinite: root.attribute_or("infinite", 0)? == 1,
+        orientation,
+        render_order: RenderOrder::parse(root.node.attribute("renderorder").unwrap_or_default()),
+        tile_size: (root.attribute("tilewidth")?, root.attribute("tileheight")?),
+        parallax_origin: (
+            root.attribute_or("parallaxoriginx", 0.0)?,
+            root.attribute_or("parallaxoriginy", 0.0)?,
+        ),
+        properties: parse_properties(root)?,
+        layers,
+        tilesets,
+    })
+}
+
+/// Loads an external `.tsx` tileset, its image is resolved relative to the `.tsx` file.
+pub async fn load_tileset(path: &str, first_gid: u32) -> Result<TileSet, MapError> {
+    let text = load_xml(path).await?;
+    let document = parse_xml(path, &text)?;
+    parse_tileset(
+        Element {
+            file: path,
+            node: document.root_element(),
+        },
+        first_gid,
+    )
+    .await
+}
+
+async fn parse_tileset(element: Element<'_, '_>, first_gid: u32) -> Result<TileSet, MapError> {
+    let image = element.required_child("image")?;
+    let image_path = resolve_path(element.file, image.str("source")?);
+
+    Ok(TileSet {
+        name: element.attribute_or("name", String::new())?,
+        first_gid,
+        image: load_image(&image_path).await?.0,
+        image_path,
+        columns: element.attribute("columns")?,
+        tile_count: element.attribute("tilecount")?,
+        tile_size: (
+            element.attribute("tilewidth")?,
+            element.attribute("tileheight")?,
+        ),
+        margin: element.attribute_or("margin", 0)?,
+        spacing: element.attribute_or("spacing", 0)?,
+        image_size: (image.attribute("width")?, image.attribute("height")?),
+        properties: parse_properties(element)?,
+        tiles: element
+            .children("tile")
+            .map(|tile| {
+                Ok((
+                    tile.attribute("id")?,
+                    TileData {
+                        properties: parse_properties(tile)?,
+                        animation: tile
+                            .children("animation")
+                            .flat_map(|animation| animation.children("frame"))
+                            .map(|frame| {
+                                Ok(Frame {
+                                    tile_id: frame.attribute("tileid")?,
+                                    duration: std::time::Duration::from_millis(
+                                        frame.attribute("duration")?,
+                                    ),
+                                })
+                            })
+                            .collect::<Result<_, MapError>>()?,
+                        objects: tile
+                            .children("objectgroup")
+                            .flat_map(|objectgroup| objectgroup.children("object"))
+                            .map(parse_object)
+                            .collect::<Result<_, MapError>>()?,
+                    },
+                ))
+            })
+            .collect::<Result<_, MapError>>()?,
+        wang_sets: element
+            .children("wangsets")
+            .flat_map(|wang_sets| wang_sets.children("wangset"))
+            .map(parse_wang_set)
+            .collect::<Result<_, MapError>>()?,
+    })
+}
+
+fn parse_wang_set(element: Element) -> Result<WangSet, MapError> {
+    // -1 when there is no tile
+    let tile = |element: Element| Ok(u32::try_from(element.attribute_or("tile", -1i64)?).ok());
+    Ok(WangSet {
+        name: element.attribute_or("name", String::new())?,
+        class: element.attribute_or("class", String::new())?,
+        kind: WangSetType::parse(element.str("type")?)
+            .ok_or_else(|| element.invalid("type", "corner, edge or mixed"))?,
+        tile: tile(element)?,
+        colors: element
+            .children("wangcolor")
+            .map(|color| {
+                Ok(WangColor {
+                    name: color.attribute_or("name", String::new())?,
+                    class: color.attribute_or("class", String::new())?,
+                    color: parse_color(color.str("color")?)
+                        .ok_or_else(|| color.invalid("color", COLOR))?,
+                    tile: tile(color)?,
+                    probability: color.attribute_or("probability", 1.0)?,
+                    properties: parse_properties(color)?,
+                })
+            })
+            .collect::<Result<_, MapError>>()?,
+        tiles: element
+            .children("wangtile")
+            .map(|tile| {
+                let colors = tile
+                    .str("wangid")?
+                    .split(',')
+                    .map(|color| tile.parse("wangid", color))
+                    .collect::<Result<Vec<u8>, MapError>>()?;
+                Ok((
+                    tile.attribute("tileid")?,
+                    colors
+                        .try_into()
+                        .map_err(|_| tile.invalid("wangid", "8 wang colors"))?,
+                ))
+            })
+            .collect::<Result<_, MapError>>()?,
+        properties: parse_properties(element)?,
+    })
+}
+
+/// The layer elements of a map or `<group>`, groups are parsed recursively.
+fn parse_layers<'a>(
+    element: Element<'a, '_>,
+) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Vec<Layers>, MapError>> + 'a>> {
+    Box::pin(async move {
+        let mut layers = vec![];
+        for node in element.node.children().filter(|node| node.is_element()) {
+            let element = Element {
+                file: element.file,
+                node,
+            };
+            let tag = node.tag_name().name();
+            if !matches!(tag, "layer" | "objectgroup" | "imagelayer" | "group") {
+                continue;
+            }
+
+            let id = element.attribute("id")?;
+            let name = element.attribute_or("name", String::new())?;
+            let visible = element.attribute_or("visible", 1)? == 1;
+            let attributes = parse_attributes(element)?;
+            let properties = parse_properties(element)?;
+
+            layers.push(match tag {
+                "layer" => {
+                    let data = element.required_child("data")?;
+                    let chunks = data
+                        .children("chunk")
+                        .map(|chunk| {
+                            Ok(Chunk {
+                                position: (chunk.attribute("x")?, chunk.attribute("y")?),
+                                size: (chunk.attribute("width")?, chunk.attribute("height")?),
+                                data: parse_data(data, chunk)?,
+                            })
+                        })
+                        .collect::<Result<Vec<Chunk>, MapError>>()?;
+
+                    Layers::TileLayer {
+                        id,
+                        name,
+                        visible,
+                        attributes,
+                        properties,
+                        data: match chunks.is_empty() {
+                            true => parse_data(data, data)?,
+                            false => vec![],
+                        },
+                        chunks,
+                    }
+                }
+                "objectgroup" => Layers::ObjectGroup {
+                    id,
+                    name,
+                    visible,
+                    attributes,
+                    properties,
+                    objects: element
+                        .children("object")
+                        .map(parse_object)
+                        .collect::<Result<Vec<Object>, MapError>>()?,
+                },
+                "imagelayer" => {
+                    let image_path = match element
+                        .child("image")
+                        .and_then(|image| image.node.attribute("source"))
+                    {
+                        None | Some("") => String::new(),
+                        Some(source) => resolve_path(element.file, source),
+                    };
+                    let (image, image_size) = match image_path.as_str() {
+                        "" => (vec![], (0, 0)),
+                        image_path => load_image(image_path).await?,
+                    };
+                    Layers::ImageLayer {
+                        id,
+                        name,
+                        visible,
+                        attributes,
+                        properties,
+                        image,
+                        image_path,
+                        image_size,
+                        repeat: (
+                            element.attribute_or("repeatx", 0)? == 1,
+                            element.attribute_or("repeaty", 0)? == 1,
+                        ),
+                    }
+                }
+                _ => Layers::Group {
+                    id,
+                    name,
+                    visible,
+                    attributes,
+                    properties,
+                    layers: parse_layers(element).await?,
+                },
+            });
+        }
+        Ok(layers)
+    })
+}
+
+fn parse_attributes(element: Element) -> Result<LayerAttributes, MapError> {
+    let default = LayerAttributes::default();
+    Ok(LayerAttributes {
+        opacity: element.attribute_or("opacity", default.opacity)?,
+        tint_color: match element.node.attribute("tintcolor") {
+            Some(color) => parse_color(color).ok_or_else(|| element.invalid("tintcolor", COLOR))?,
+            None => default.tint_color,
+        },
+        offset: (
+            element.attribute_or("offsetx", default.offset.0)?,
+            element.attribute_or("offsety", default.offset.1)?,
+        ),
+        parallax: (
+            element.attribute_or("parallaxx", default.parallax.0)?,
+            element.attribute_or("parallaxy", default.parallax.1)?,
+        ),
+    })
+}
+
+/// The `<properties>` child of a map, layer, tileset, tile, object or class property.
+fn parse_properties(element: Element) -> Result<Properties, MapError> {
+    let Some(properties) = element.child("properties") else {
+        return Ok(Properties::new());
+    };
+
+    properties
+        .children("property")
+        .map(|property| {
+            // Multiline strings are stored as the element text
+            let value = match property.node.attribute("value") {
+                Some(value) => value,
+                None => property.text(),
+            };
+            let property_value = match property.attribute_or("type", String::new())?.as_str() {
+                "" | "string" => PropertyValue::String(value.to_string()),
+                "int" => PropertyValue::Int(property.parse("value", value)?),
+                "float" => PropertyValue::Float(property.parse("value", value)?),
+                "bool" => PropertyValue::Bool(property.parse("value", value)?),
+                "color" => PropertyValue::Color(match value {
+                    "" => None,
+                    _ => Some(parse_color(value).ok_or_else(|| property.invalid("value", COLOR))?),
+                }),
+                "file" => PropertyValue::File(value.to_string()),
+                "object" => PropertyValue::Object(property.attribute_or("value", 0)?),
+                "class" => PropertyValue::Class {
+                    class: property.attribute_or("propertytype", String::new())?,
+                    properties: parse_properties(property)?,
+                },
+                _ => return Err(property.invalid("type", "a property type")),
+            };
+            Ok((property.str("name")?.to_string(), property_value))
+        })
+        .collect()
+}
+
+/// `data` is the `<data>` element holding the encoding, `element` is either itself or one of its `<chunk>`.
+fn parse_data(data: Element, element: Element) -> Result<Vec<Tile>, MapError> {
+    match data.node.attribute("encoding") {
+        Some("csv") => element
+            .text()
+            .split(',')
+            .map(|gid| Ok(Tile::from_raw(element.parse("csv data", gid)?)))
+            .collect(),
+        None => element
+            .children("tile")
+            .map(|tile| Ok(Tile::from_raw(tile.attribute_or("gid", 0)?)))
+            .collect(),
+        Some("base64") => decode_tile_data(
+            element.text(),
+            data.node.attribute("compression").unwrap_or_default(),
+        ),
+        Some(encoding) => Err(MapError::UnsupportedEncoding(encoding.to_string())),
+    }
+}
+
+fn parse_points(element: Element) -> Result<Vec<(f32, f32)>, MapError> {
+    element
+        .str("points")?
+        .split_whitespace()
+        .map(|point| {
+            let (x, y) = point.split_once(',').unwrap_or((point, ""));
+            Ok((element.parse("points", x)?, element.parse("points", y)?))
+        })
+        .collect()
+}
+
+fn parse_object(element: Element) -> Result<Object, MapError> {
+    let shape = if element.child("point").is_some() {
+        Shape::Point
+    } else if element.child("ellipse").is_some() {
+        Shape::Ellipse
+    } else if let Some(polygon) = element.child("polygon") {
+        Shape::Polygon(parse_points(polygon)?)
+    } else if let Some(polyline) = element.child("polyline") {
+        Shape::Polyline(parse_points(polyline)?)
+    } else {
+        Shape::Rectangle
+    };
+
+    Ok(Object {
+        name: element.attribute_or("name", String::new())?,
+        id: element.attribute("id")?,
+        class: element
+            .node
+            .attribute("class")
+            .or(element.node.attribute("type"))
+            .unwrap_or_default()
+            .to_string(),
+        position: (element.attribute("x")?, element.attribute("y")?),
+        size: (
+            element.attribute_or("width", 0.0)?,
+            element.attribute_or("height", 0.0)?,
+        ),
+        rotation: element.attribute_or("rotation", 0.0)?,
+        visible: element.attribute_or("visible", 1)? == 1,
+        shape,
+        properties: parse_properties(element)?,
+    })
+}
@@ -0,0 +1,320 @@
+use super::{Properties, Tile, TileSet};
+
+/// Which parts of the tiles a wang set matches colors on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WangSetType {
+    Corner,
+    Edge,
+    Mixed,
+}
+
+impl WangSetType {
+    pub fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "corner" => Some(WangSetType::Corner),
+            "edge" => Some(WangSetType::Edge),
+            "mixed" => Some(WangSetType::Mixed),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WangSetType::Corner => "corner",
+            WangSetType::Edge => "edge",
+            WangSetType::Mixed => "mixed",
+        }
+    }
+
+    /// Indices of a `WangId` the tiles are matched on.
+    fn indices(&self) -> impl Iterator<Item = usize> {
+        let (start, step) = match self {
+            WangSetType::Corner => (1, 2),
+            WangSetType::Edge => (0, 2),
+            WangSetType::Mixed => (0, 1),
+        };
+        (start..8).step_by(step)
+    }
+}
+
+/// Colors of the edges and corners of a tile, clockwise from the top edge: top,
+/// top-right, right, bottom-right, bottom, bottom-left, left and top-left.
+/// 0 is no color, any other value is an index in `WangSet::colors` plus one.
+pub type WangId = [u8; 8];
+
+/// A terrain of a wang set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WangColor {
+    pub name: String,
+    pub class: String,
+    pub color: [f32; 4],
+    /// Local id of the tile representing the color.
+    pub tile: Option<u32>,
+    pub probability: f32,
+    pub properties: Properties,
+}
+
+/// Terrains of a tileset, along with the tiles making the transitions between them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WangSet {
+    pub name: String,
+    pub class: String,
+    pub kind: WangSetType,
+    /// Local id of the tile representing the set.
+    pub tile: Option<u32>,
+    pub colors: Vec<WangColor>,
+    /// Keyed by local tile id.
+    pub tiles: std::collections::BTreeMap<u32, WangId>,
+    pub properties: Properties,
+}
+
+impl WangSet {
+    /// Wang id of the cell `(x, y)` of `mask`, a grid `width` cells wide of wang colors.
+    /// Each edge and corner takes the lowest color of the cells around it, so cells
+    /// are bordered by the lower colors next to them: with water as the first color,
+    /// the coast is drawn on the outer cells of the land. Cells past the grid are
+    /// taken to be like the cells at its border. An empty grid has no colors.
+    pub fn wang_id(&self, mask: &[u8], width: usize, x: usize, y: usize) -> WangId {
+        if width == 0 || mask.len() < width {
+            return [0; 8];
+        }
+        let height = mask.len() / width;
+        let color = |dx: isize, dy: isize| {
+            let x = (x as isize + dx).clamp(0, width as isize - 1) as usize;
+            let y = (y as isize + dy).clamp(0, height as isize - 1) as usize;
+            mask[x + y * width]
+        };
+        let edge = |dx: isize, dy: isize| color(0, 0).min(color(dx, dy));
+        let corner = |dx: isize, dy: isize| edge(dx, 0).min(color(0, dy)).min(color(dx, dy));
+
+        let mut wang_id = [
+            edge(0, -1),
+            corner(1, -1),
+            edge(1, 0),
+            corner(1, 1),
+            edge(0, 1),
+            corner(-1, 1),
+            edge(-1, 0),
+            corner(-1, -1),
+        ];
+        let indices = self.kind.indices().collect::<Vec<_>>();
+        for (index, color) in wang_id.iter_mut().enumerate() {
+            if !indices.contains(&index) {
+                *color = 0;
+            }
+        }
+        wang_id
+    }
+
+    /// Local id of the tile matching `wang_id` best, the one with the fewest
+    /// colors differing. Colors of 0 in `wang_id` match any color.
+    pub fn find_tile(&self, wang_id: WangId) -> Option<u32> {
+        self.tiles
+            .iter()
+            .min_by_key(|(_, tile)| {
+                self.kind
+                    .indices()
+                    .filter(|&index| wang_id[index] != 0 && tile[index] != wang_id[index])
+                    .count()
+            })
+            .map(|(&id, _)| id)
+    }
+}
+
+impl TileSet {
+    /// Tiles of wang set `wang_set` for `mask`, a grid `width` cells wide of its
+    /// colors, see `WangSet::wang_id`. Cells without a color are left empty, as
+    /// are all of them when `width` is 0.
+    pub fn autotile(&self, wang_set: usize, mask: &[u8], width: usize) -> Vec<Tile> {
+        let wang_set = &self.wang_sets[wang_set];
+        if width == 0 {
+            return vec![Tile::default(); mask.len()];
+        }
+        (0..mask.len())
+            .map(|cell| match mask[cell] {
+                0 => Tile::default(),
+                _ => wang_set
+                    .find_tile(wang_set.wang_id(mask, width, cell % width, cell / width))
+                    .map(|id| Tile {
+                        gid: self.first_gid + id,
+                        ..Default::default()
+                    })
+                    .unwrap_or_default(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WATER: u8 = 1;
+    const LAND: u8 = 2;
+    const NEIGHBOURS: [(isize, isize); 8] = [
+        (0, -1),
+        (1, -1),
+        (1, 0),
+        (1, 1),
+        (0, 1),
+        (-1, 1),
+        (-1, 0),
+        (-1, -1),
+    ];
+
+    /// Shore of an island, a 3×3 block of tiles 0 to 8 with land in its middle,
+    /// and tile 9 of water.
+    fn shore() -> WangSet {
+        let mut tiles = std::collections::BTreeMap::new();
+        for (dx, dy) in (0..3).flat_map(|dy| (0..3).map(move |dx| (dx, dy))) {
+            let corner = |land: bool| if land { LAND } else { WATER };
+            let (left, right, top, bottom) = (dx > 0, dx < 2, dy > 0, dy < 2);
+            tiles.insert(
+                dx + dy * 3,
+                [
+                    0,
+                    corner(right && top),
+                    0,
+                    corner(right && bottom),
+                    0,
+                    corner(left && bottom),
+                    0,
+                    corner(left && top),
+                ],
+            );
+        }
+        tiles.insert(9, [0, WATER, 0, WATER, 0, WATER, 0, WATER]);
+        wang_set(WangSetType::Corner, tiles)
+    }
+
+    fn wang_set(kind: WangSetType, tiles: std::collections::BTreeMap<u32, WangId>) -> WangSet {
+        let color = |name: &str| WangColor {
+            name: name.to_string(),
+            class: String::new(),
+            color: [0.0; 4],
+            tile: None,
+            probability: 1.0,
+            properties: Properties::new(),
+        };
+        WangSet {
+            name: "shore".to_string(),
+            class: String::new(),
+            kind,
+            tile: None,
+            colors: vec![color("water"), color("land")],
+            tiles,
+            properties: Properties::new(),
+        }
+    }
+
+    fn tileset(wang_set: WangSet) -> TileSet {
+        TileSet {
+            name: "shore".to_string(),
+            first_gid: 1,
+            image: vec![],
+            image_path: String::new(),
+            columns: 10,
+            tile_count: 10,
+            tile_size: (16, 16),
+            margin: 0,
+            spacing: 0,
+            image_size: (160, 16),
+            properties: Properties::new(),
+            tiles: Default::default(),
+            wang_sets: vec![wang_set],
+        }
+    }
+
+    /// 3×3 grid of land with water on the neighbour `(dx, dy)` of its middle.
+    fn water_at(dx: isize, dy: isize) -> Vec<u8> {
+        let mut mask = vec![LAND; 9];
+        mask[((1 + dx) + (1 + dy) * 3) as usize] = WATER;
+        mask
+    }
+
+    #[test]
+    fn corners_take_the_lowest_color_around_them() {
+        let wang_set = shore();
+        for (dx, dy) in NEIGHBOURS {
+            let wang_id = wang_set.wang_id(&water_at(dx, dy), 3, 1, 1);
+            for (index, &(x, y)) in NEIGHBOURS.iter().enumerate() {
+                let expected = match index % 2 {
+                    0 => 0,
+                    _ if (dx == 0 || dx == x) && (dy == 0 || dy == y) => WATER,
+                    _ => LAND,
+                };
+                assert_eq!(wang_id[index], expected, "water at {:?}", (dx, dy));
+            }
+        }
+    }
+
+    #[test]
+    fn edges_take_the_color_next_to_them() {
+        let wang_set = wang_set(WangSetType::Edge, Default::default());
+        for (dx, dy) in NEIGHBOURS {
+            let wang_id = wang_set.wang_id(&water_at(dx, dy), 3, 1, 1);
+            for (index, &neighbour) in NEIGHBOURS.iter().enumerate() {
+                let expected = match index % 2 {
+                    0 if neighbour == (dx, dy) => WATER,
+                    0 => LAND,
+                    _ => 0,
+                };
+                assert_eq!(wang_id[index], expected, "water at {:?}", (dx, dy));
+            }
+        }
+    }
+
+    #[test]
+    fn cells_past_the_map_are_like_its_border() {
+        let wang_set = shore();
+        let land = [0, LAND, 0, LAND, 0, LAND, 0, LAND];
+        assert_eq!(wang_set.wang_id(&[LAND], 1, 0, 0), land);
+        assert_eq!(
+            wang_set.wang_id(&[LAND, WATER], 2, 0, 0),
+            [0, WATER, 0, WATER, 0, LAND, 0, LAND]
+        );
+        assert_eq!(
+            wang_set.wang_id(&[WATER, LAND], 1, 0, 1),
+            [0, WATER, 0, LAND, 0, LAND, 0, WATER]
+        );
+    }
+
+    #[test]
+    fn autotiles_the_shore_of_an_island() {
+        let tileset = tileset(shore());
+        let mut mask = vec![WATER; 25];
+        for (x, y) in (1..4).flat_map(|y| (1..4).map(move |x| (x, y))) {
+            mask[x + y * 5] = LAND;
+        }
+        let gids = tileset
+            .autotile(0, &mask, 5)
+            .iter()
+            .map(|tile| tile.gid)
+            .collect::<Vec<_>>();
+        #[rustfmt::skip]
+        assert_eq!(gids, [
+            10, 10, 10, 10, 10,
+            10,  1,  2,  3, 10,
+            10,  4,  5,  6, 10,
+            10,  7,  8,  9, 10,
+            10, 10, 10, 10, 10,
+        ]);
+    }
+
+    #[test]
+    fn cells_without_a_color_are_empty() {
+        let tileset = tileset(shore());
+        let tiles = tileset.autotile(0, &[0, LAND, WATER, LAND], 2);
+        assert_eq!(tiles[0], Tile::default());
+        assert!(tiles[1..].iter().all(|tile| tile.gid != 0));
+    }
+
+    #[test]
+    fn empty_grids_have_no_tiles() {
+        assert_eq!(shore().wang_id(&[], 0, 0, 0), [0; 8]);
+        assert_eq!(
+            tileset(shore()).autotile(0, &[LAND, WATER], 0),
+            vec![Tile::default(); 2]
+        );
+    }
+}
@@ -0,0 +1,300 @@
+use super::{Map, MapError, Object, PropertyValue};
+
+/// Class of the objects taking the player to another map.
+const PORTAL_CLASS: &str = "portal";
+
+/// File property of a portal with the map it leads to, relative to its own map.
+const MAP_PROPERTY: &str = "map";
+
+/// String property of a portal with the object the player appears at in the
+/// target map, its spawn point when unset.
+const SPAWN_PROPERTY: &str = "spawn";
+
+/// Float property of a portal with the seconds the screen takes to fade out, and
+/// then in, when going through it. Zero switches maps at once.
+const FADE_PROPERTY: &str = "fade";
+
+const DEFAULT_FADE: std::time::Duration = std::time::Duration::from_millis(300);
+
+/// Where a portal object leads to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Portal {
+    /// Path of the target map, as loaded by `World::load`.
+    pub map: String,
+    pub spawn: Option<String>,
+    pub fade: std::time::Duration,
+}
+
+impl Portal {
+    /// Portal of `object`, of the map at `path`, when it's one.
+    pub fn from_object(path: &str, object: &Object) -> Option<Self> {
+        if object.class != PORTAL_CLASS {
+            return None;
+        }
+        let target = object
+            .properties
+            .get(MAP_PROPERTY)
+            .and_then(PropertyValue::as_str)?;
+
+        Some(Self {
+            map: super::resolve_path(path, target),
+            spawn: object
+                .properties
+                .get(SPAWN_PROPERTY)
+                .and_then(PropertyValue::as_str)
+                .map(str::to_string),
+            fade: object
+                .properties
+                .get(FADE_PROPERTY)
+                .and_then(PropertyValue::as_float)
+                .map_or(DEFAULT_FADE, |fade| {
+                    std::time::Duration::from_secs_f64(fade.max(0.0))
+                }),
+        })
+    }
+}
+
+/// Maps the game can switch between through portals, keyed by path with `.` and
+/// `..` collapsed. The map being played is kept by the game, the world keeps the rest.
+pub struct World {
+    maps: std::collections::HashMap<String, Map>,
+    /// Path of the map being played.
+    pub current: String,
+}
+
+impl World {
+    /// A world playing the map at `path` alone, like a generated map.
+    pub fn new(path: &str) -> Self {
+        Self {
+            maps: std::collections::HashMap::new(),
+            current: super::normalize_path(path),
+        }
+    }
+
+    /// Loads the map at `path`, returned to be played, and every map reachable
+    /// from it through portals.
+    pub async fn load(path: &str) -> Result<(Self, Map), MapError> {
+        let mut world = Self::new(path);
+        let mut pending = vec![world.current.clone()];
+        while let Some(path) = pending.pop() {
+            if world.maps.contains_key(&path) {
+                continue;
+            }
+            let map = super::load_map(&path).await?;
+            pending.extend(
+                map.objects()
+                    .filter_map(|object| Portal::from_object(&path, object))
+                    .map(|portal| portal.map),
+            );
+            world.maps.insert(path, map);
+        }
+
+        let map = world
+            .maps
+            .remove(&world.current)
+            .expect("the first map was loaded");
+        Ok((world, map))
+    }
+
+    /// Replaces `map`, the map being played, with the map at `path`. The world
+    /// keeps `map` with the changes made while it was played. Returns false when
+    /// the world has no map at `path`.
+    pub fn switch(&mut self, map: &mut Map, path: &str) -> bool {
+        if path == self.current {
+            return true;
+        }
+        let Some(next) = self.maps.remove(path) else {
+            return false;
+        };
+        let previous = std::mem::replace(map, next);
+        self.maps.insert(
+            std::mem::replace(&mut self.current, path.to_string()),
+            previous,
+        );
+        true
+    }
+
+    /// Portals of `map`, the map being played, with their area in world units.
+    pub fn portals(&self, map: &Map) -> Vec<(Portal, parry2d::bounding_volume::Aabb)> {
+        map.objects()
+            .filter_map(|object| {
+                let portal = Portal::from_object(&self.current, object)?;
+                let corners = [
+                    object.position,
+                    (
+                        object.position.0 + object.size.0,
+                        object.position.1 + object.size.1,
+                    ),
+                ]
+                .map(|corner| {
+                    let corner = map.object_to_world(corner);
+                    nalgebra::Point2::new(corner.x, corner.y)
+                });
+                Some((
+                    portal,
+                    parry2d::bounding_volume::Aabb::from_points(&corners),
+                ))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Map, Object, PropertyValue};
+
+    /// Where the tests write the maps of their worlds.
+    const WORLD: &str = "./target/world-tests";
+
+    fn portal(properties: &[(&str, PropertyValue)]) -> Object {
+        Object {
+            name: "door".to_string(),
+            id: 1,
+            class: "portal".to_string(),
+            position: (16.0, 16.0),
+            size: (16.0, 16.0),
+            rotation: 0.0,
+            visible: true,
+            shape: super::super::Shape::Rectangle,
+            properties: properties
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.clone()))
+                .collect(),
+        }
+    }
+
+    /// Writes a 2x2 map at `path` under `WORLD` with a portal to `target`.
+    fn write_map(path: &str, target: &str) -> String {
+        let path = format!("{}/{}", WORLD, path);
+        let map = serde_json::json!({
+            "type": "map",
+            "orientation": "orthogonal",
+            "renderorder": "right-down",
+            "width": 2,
+            "height": 2,
+            "tilewidth": 16,
+            "tileheight": 16,
+            "infinite": false,
+            "tilesets": [],
+            "layers": [{
+                "id": 1,
+                "name": "portals",
+                "type": "objectgroup",
+                "visible": true,
+                "x": 0,
+                "y": 0,
+                "opacity": 1,
+                "objects": [{
+                    "id": 1,
+                    "name": "door",
+                    "type": "portal",
+                    "x": 0,
+                    "y": 0,
+                    "width": 16,
+                    "height": 16,
+                    "rotation": 0,
+                    "visible": true,
+                    "properties": [{ "name": "map", "type": "file", "value": target }],
+                }],
+            }],
+        });
+        std::fs::create_dir_all(std::path::Path::new(&path).parent().unwrap()).unwrap();
+        std::fs::write(&path, map.to_string()).unwrap();
+        path
+    }
+
+    /// An overworld and a house in a directory below it, each with a portal to the
+    /// other, in their own `test` directory so tests don't overwrite each other's maps.
+    fn load_cycle(test: &str) -> (super::World, Map) {
+        let overworld = write_map(&format!("{}/overworld.json", test), "interiors/house.json");
+        write_map(
+            &format!("{}/interiors/house.json", test),
+            "../overworld.json",
+        );
+        pollster::block_on(super::World::load(&overworld)).unwrap()
+    }
+
+    #[test]
+    fn portals_need_their_class_and_map() {
+        let mut object = portal(&[("map", PropertyValue::File("house.json".to_string()))]);
+        assert!(super::Portal::from_object("./resources/mapa.json", &object).is_some());
+        assert_eq!(
+            super::Portal::from_object("./resources/mapa.json", &portal(&[])),
+            None
+        );
+
+        object.class = "door".to_string();
+        assert_eq!(
+            super::Portal::from_object("./resources/mapa.json", &object),
+            None
+        );
+    }
+
+    #[test]
+    fn portals_default_to_the_spawn_point_and_a_short_fade() {
+        let object = portal(&[("map", PropertyValue::File("../mapa.json".to_string()))]);
+        assert_eq!(
+            super::Portal::from_object("./resources/interiors/house.json", &object),
+            Some(super::Portal {
+                map: "./resources/mapa.json".to_string(),
+                spawn: None,
+                fade: super::DEFAULT_FADE,
+            })
+        );
+    }
+
+    #[test]
+    fn portals_read_their_spawn_and_fade() {
+        let object = portal(&[
+            ("map", PropertyValue::File("house.json".to_string())),
+            ("spawn", PropertyValue::String("door".to_string())),
+            ("fade", PropertyValue::Float(1.5)),
+        ]);
+        assert_eq!(
+            super::Portal::from_object("./resources/mapa.json", &object),
+            Some(super::Portal {
+                map: "./resources/house.json".to_string(),
+                spawn: Some("door".to_string()),
+                fade: std::time::Duration::from_millis(1500),
+            })
+        );
+
+        let object = portal(&[
+            ("map", PropertyValue::File("house.json".to_string())),
+            ("fade", PropertyValue::Float(-1.0)),
+        ]);
+        assert_eq!(
+            super::Portal::from_object("./resources/mapa.json", &object)
+                .unwrap()
+                .fade,
+            std::time::Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn loads_each_map_of_a_cycle_once() {
+        let (world, map) = load_cycle("cycle");
+
+        let house = format!("{}/cycle/interiors/house.json", WORLD);
+        assert_eq!(world.current, format!("{}/cycle/overworld.json", WORLD));
+        assert_eq!(world.maps.keys().collect::<Vec<_>>(), [&house]);
+        assert_eq!(world.portals(&map)[0].0.map, house);
+    }
+
+    #[test]
+    fn switching_keeps_the_changes_to_the_previous_map() {
+        let (mut world, mut map) = load_cycle("switch");
+        let house = world.portals(&map)[0].0.map.clone();
+        map.size = (3, 3);
+
+        assert!(world.switch(&mut map, &house));
+        assert_eq!(map.size, (2, 2));
+        let overworld = world.portals(&map)[0].0.map.clone();
+        assert_eq!(overworld, format!("{}/switch/overworld.json", WORLD));
+
+        assert!(world.switch(&mut map, &overworld));
+        assert_eq!(map.size, (3, 3));
+        assert_eq!(world.current, overworld);
+        assert!(!world.switch(&mut map, "./resources/missing.json"));
+    }
+}
@@ -0,0 +1,494 @@
+use super::{
+    LayerAttributes, Layers, Map, MapError, Object, Properties, PropertyValue, Shape, TileSet,
+    WangSet,
+};
+use serde_json::{json, Value};
+
+/// Version of the Tiled JSON format written.
+const VERSION: &str = "1.10";
+
+/// Saves `map` as a Tiled JSON map at `path`, along with the images of its
+/// generated tilesets, see `exported_image_path`.
+pub fn save_map(map: &Map, path: &str) -> Result<(), MapError> {
+    let io_error = |path: &str, message: String| MapError::Io {
+        path: path.to_string(),
+        message,
+    };
+    let text = serde_json::to_string(&to_json(map, path))
+        .map_err(|error| io_error(path, error.to_string()))?;
+
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "wasm32")] {
+            let _ = text;
+            Err(io_error(path, "saving files isn't supported on the web".to_string()))
+        } else {
+            for tileset in map.tilesets.iter().filter(|tileset| tileset.image_path.is_empty()) {
+                let image_path = exported_image_path(path, tileset);
+                std::fs::write(&image_path, &tileset.image)
+                    .map_err(|error| io_error(&image_path, error.to_string()))?;
+            }
+            std::fs::write(path, text).map_err(|error| io_error(path, error.to_string()))
+        }
+    }
+}
+
+/// Where the image of a generated tileset, like the ones of LDtk IntGrid layers,
+/// is saved along with the map at `path`: next to it, named after both.
+fn exported_image_path(path: &str, tileset: &TileSet) -> String {
+    let path = std::path::Path::new(path);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{}.{}.png", stem, tileset.name))
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Tiled JSON of `map`, with image paths relative to `path`, where it will be saved.
+/// Tilesets are always embedded, even the ones loaded from their own file.
+pub fn to_json(map: &Map, path: &str) -> Value {
+    let all_layers = map.all_layers();
+    let next_layer_id = all_layers
+        .iter()
+        .map(|layer| layer_id(layer))
+        .max()
+        .unwrap_or(0)
+        + 1;
+    let next_object_id = map.objects().map(|object| object.id).max().unwrap_or(0) + 1;
+
+    let mut value = json!({
+        "type": "map",
+        "version": VERSION,
+        "compressionlevel": -1,
+        "orientation": map.orientation.name(),
+        "renderorder": map.render_order.name(),
+        "width": map.size.0,
+        "height": map.size.1,
+        "tilewidth": map.tile_size.0,
+        "tileheight": map.tile_size.1,
+        "infinite": map.infinite,
+        "nextlayerid": next_layer_id,
+        "nextobjectid": next_object_id,
+        "layers": map.layers.iter().map(|layer| layer_json(map, layer, path)).collect::<Vec<_>>(),
+        "tilesets": map.tilesets.iter().map(|tileset| tileset_json(tileset, path)).collect::<Vec<_>>(),
+    });
+    if let Some((axis, index, side_length)) = map.orientation.stagger_fields() {
+        value["staggeraxis"] = axis.into();
+        value["staggerindex"] = index.into();
+        if map.orientation.name() == "hexagonal" {
+            value["hexsidelength"] = side_length.into();
+        }
+    }
+    if map.parallax_origin != (0.0, 0.0) {
+        value["parallaxoriginx"] = map.parallax_origin.0.into();
+        value["parallaxoriginy"] = map.parallax_origin.1.into();
+    }
+    insert_properties(&mut value, &map.properties);
+    value
+}
+
+fn layer_id(layer: &Layers) -> u32 {
+    match layer {
+        Layers::ObjectGroup { id, .. }
+        | Layers::TileLayer { id, .. }
+        | Layers::ImageLayer { id, .. }
+        | Layers::Group { id, .. } => *id,
+    }
+}
+
+fn layer_json(map: &Map, layer: &Layers, path: &str) -> Value {
+    let mut value = json!({
+        "id": layer_id(layer),
+        "name": layer.name(),
+        "visible": layer.visible(),
+        "x": 0,
+        "y": 0,
+    });
+    insert_attributes(&mut value, layer.attributes());
+    insert_properties(&mut value, layer.properties());
+
+    match layer {
+        Layers::TileLayer { chunks, .. } if map.infinite => {
+            let start = (
+                chunks
+                    .iter()
+                    .map(|chunk| chunk.position.0)
+                    .min()
+                    .unwrap_or(0),
+                chunks
+                    .iter()
+                    .map(|chunk| chunk.position.1)
+                    .min()
+                    .unwrap_or(0),
+            );
+            let end = (
+                chunks
+                    .iter()
+                    .map(|chunk| chunk.position.0 + chunk.size.0 as i32)
+                    .max()
+                    .unwrap_or(0),
+                chunks
+                    .iter()
+                    .map(|chunk| chunk.position.1 + chunk.size.1 as i32)
+                    .max()
+                    .unwrap_or(0),
+            );
+
+            value["type"] = "tilelayer".into();
+            value["startx"] = start.0.into();
+            value["starty"] = start.1.into();
+            value["width"] = (end.0 - start.0).into();
+            value["height"] = (end.1 - start.1).into();
+            value["chunks"] = chunks
+                .iter()
+                .map(|chunk| {
+                    json!({
+                        "x": chunk.position.0,
+                        "y": chunk.position.1,
+                        "width": chunk.size.0,
+                        "height": chunk.size.1,
+                        "data": chunk.data.iter().map(|tile| tile.to_raw()).collect::<Vec<_>>(),
+                    })
+                })
+                .collect();
+        }
+        Layers::TileLayer { data, .. } => {
+            value["type"] = "tilelayer".into();
+            value["width"] = map.size.0.into();
+            value["height"] = map.size.1.into();
+            value["data"] = match data.is_empty() {
+                true => vec![0; (map.size.0 * map.size.1) as usize].into(),
+                false => data.iter().map(|tile| tile.to_raw()).collect(),
+            };
+        }
+        Layers::ObjectGroup { objects, .. } => {
+            value["type"] = "objectgroup".into();
+            value["draworder"] = "topdown".into();
+            value["objects"] = objects.iter().map(object_json).collect();
+        }
+        Layers::ImageLayer {
+            image_path,
+            image_size,
+            repeat,
+            ..
+        } => {
+            value["type"] = "imagelayer".into();
+            value["image"] = match image_path.as_str() {
+                "" => "".into(),
+                image_path => relative_path(path, image_path).into(),
+            };
+            value["imagewidth"] = image_size.0.into();
+            value["imageheight"] = image_size.1.into();
+            value["repeatx"] = repeat.0.into();
+            value["repeaty"] = repeat.1.into();
+        }
+        Layers::Group { layers, .. } => {
+            value["type"] = "group".into();
+            value["layers"] = layers
+                .iter()
+                .map(|layer| layer_json(map, layer, path))
+                .collect();
+        }
+    }
+    value
+}
+
+/// Generated tilesets refer to the image `save_map` exports for them.
+fn tileset_json(tileset: &TileSet, path: &str) -> Value {
+    let image = match tileset.image_path.as_str() {
+        "" => relative_path(path, &exported_image_path(path, tileset)),
+        image_path => relative_path(path, image_path),
+    };
+    let mut value = json!({
+        "firstgid": tileset.first_gid,
+        "name": tileset.name,
+        "image": image,
+        "imagewidth": tileset.image_size.0,
+        "imageheight": tileset.image_size.1,
+        "columns": tileset.columns,
+        "tilecount": tileset.tile_count,
+        "tilewidth": tileset.tile_size.0,
+        "tileheight": tileset.tile_size.1,
+        "margin": tileset.margin,
+        "spacing": tileset.spacing,
+    });
+    insert_properties(&mut value, &tileset.properties);
+
+    if !tileset.tiles.is_empty() {
+        value["tiles"] = tileset
+            .tiles
+            .iter()
+            .map(|(id, tile)| {
+                let mut value = json!({ "id": id });
+                insert_properties(&mut value, &tile.properties);
+                if !tile.animation.is_empty() {
+                    value["animation"] = tile
+                        .animation
+                        .iter()
+                        .map(|frame| {
+                            json!({
+                                "tileid": frame.tile_id,
+                                "duration": frame.duration.as_millis() as u64,
+                            })
+                        })
+                        .collect();
+                }
+                if !tile.objects.is_empty() {
+                    value["objectgroup"] = json!({
+                        "type": "objectgroup",
+                        "draworder": "index",
+                        "name": "",
+                        "opacity": 1,
+                        "visible": true,
+                        "x": 0,
+                        "y": 0,
+                        "objects": tile.objects.iter().map(object_json).collect::<Vec<_>>(),
+                    });
+                }
+                value
+            })
+            .collect();
+    }
+    if !tileset.wang_sets.is_empty() {
+        value["wangsets"] = tileset.wang_sets.iter().map(wang_set_json).collect();
+    }
+    value
+}
+
+fn wang_set_json(wang_set: &WangSet) -> Value {
+    let tile = |tile: Option<u32>| tile.map_or(-1, i64::from);
+    let mut value = json!({
+        "name": wang_set.name,
+        "class": wang_set.class,
+        "type": wang_set.kind.name(),
+        "tile": tile(wang_set.tile),
+        "colors": wang_set
+            .colors
+            .iter()
+            .map(|color| {
+                let mut value = json!({
+                    "name": color.name,
+                    "class": color.class,
+                    "color": color_string(color.color),
+                    "tile": tile(color.tile),
+                    "probability": color.probability,
+                });
+                insert_properties(&mut value, &color.properties);
+                value
+            })
+            .collect::<Vec<_>>(),
+        "wangtiles": wang_set
+            .tiles
+            .iter()
+            .map(|(id, wang_id)| json!({ "tileid": id, "wangid": wang_id }))
+            .collect::<Vec<_>>(),
+    });
+    insert_properties(&mut value, &wang_set.properties);
+    value
+}
+
+fn object_json(object: &Object) -> Value {
+    let points = |points: &[(f32, f32)]| {
+        points
+            .iter()
+            .map(|(x, y)| json!({ "x": x, "y": y }))
+            .collect::<Value>()
+    };
+
+    let mut value = json!({
+        "id": object.id,
+        "name": object.name,
+        "type": object.class,
+        "x": object.position.0,
+        "y": object.position.1,
+        "width": object.size.0,
+        "height": object.size.1,
+        "rotation": object.rotation,
+        "visible": object.visible,
+    });
+    match &object.shape {
+        Shape::Rectangle => {}
+        Shape::Point => value["point"] = true.into(),
+        Shape::Ellipse => value["ellipse"] = true.into(),
+        Shape::Polygon(polygon) => value["polygon"] = points(polygon),
+        Shape::Polyline(polyline) => value["polyline"] = points(polyline),
+    }
+    insert_properties(&mut value, &object.properties);
+    value
+}
+
+/// Like Tiled, only the attributes that aren't the default are written.
+fn insert_attributes(value: &mut Value, attributes: &LayerAttributes) {
+    let default = LayerAttributes::default();
+    value["opacity"] = attributes.opacity.into();
+    if attributes.tint_color != default.tint_color {
+        value["tintcolor"] = color_string(attributes.tint_color).into();
+    }
+    if attributes.offset != default.offset {
+        value["offsetx"] = attributes.offset.0.into();
+        value["offsety"] = attributes.offset.1.into();
+    }
+    if attributes.parallax != default.parallax {
+        value["parallaxx"] = attributes.parallax.0.into();
+        value["parallaxy"] = attributes.parallax.1.into();
+    }
+}
+
+fn insert_properties(value: &mut Value, properties: &Properties) {
+    if properties.is_empty() {
+        return;
+    }
+
+    value["properties"] = properties
+        .iter()
+        .map(|(name, property)| {
+            let property_type = match property {
+                PropertyValue::String(_) => "string",
+                PropertyValue::Int(_) => "int",
+                PropertyValue::Float(_) => "float",
+                PropertyValue::Bool(_) => "bool",
+                PropertyValue::Color(_) => "color",
+                PropertyValue::File(_) => "file",
+                PropertyValue::Object(_) => "object",
+                PropertyValue::Class { .. } => "class",
+            };
+            let mut value = json!({
+                "name": name,
+                "type": property_type,
+                "value": property_value(property),
+            });
+            if let PropertyValue::Class { class, .. } = property {
+                value["propertytype"] = class.as_str().into();
+            }
+            value
+        })
+        .collect();
+}
+
+/// Class members are written as a plain object, their types come from the
+/// project file in Tiled.
+fn property_value(property: &PropertyValue) -> Value {
+    match property {
+        PropertyValue::String(value) | PropertyValue::File(value) => value.as_str().into(),
+        PropertyValue::Int(value) => (*value).into(),
+        PropertyValue::Float(value) => (*value).into(),
+        PropertyValue::Bool(value) => (*value).into(),
+        PropertyValue::Color(Some(color)) => color_string(*color).into(),
+        PropertyValue::Color(None) => "".into(),
+        PropertyValue::Object(id) => (*id).into(),
+        PropertyValue::Class { properties, .. } => properties
+            .iter()
+            .map(|(name, member)| (name.clone(), property_value(member)))
+            .collect::<serde_json::Map<_, _>>()
+            .into(),
+    }
+}
+
+/// `#RRGGBB`, or `#AARRGGBB` when the color is translucent.
+fn color_string(color: [f32; 4]) -> String {
+    let [r, g, b, a] = color.map(|channel| (channel * 255.0).round() as u8);
+    match a {
+        255 => format!("#{:02x}{:02x}{:02x}", r, g, b),
+        _ => format!("#{:02x}{:02x}{:02x}{:02x}", a, r, g, b),
+    }
+}
+
+/// `path` relative to the directory of the file at `base`, the inverse of `resolve_path`.
+fn relative_path(base: &str, path: &str) -> String {
+    let components = |path: &std::path::Path| {
+        path.components()
+            .filter(|component| *component != std::path::Component::CurDir)
+            .map(|component| component.as_os_str().to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+    };
+    let base = components(std::path::Path::new(base).parent().unwrap_or("".as_ref()));
+    let path = components(std::path::Path::new(path));
+
+    let common = base
+        .iter()
+        .zip(&path)
+        .take_while(|(base, path)| base == path)
+        .count();
+    (common..base.len())
+        .map(|_| "..".to_string())
+        .chain(path[common..].iter().cloned())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    const MAP: &str = "./resources/mapa.json";
+    /// Where the tests save maps, away from the resources they load.
+    const SAVED: &str = "./target/writer-tests";
+
+    fn load(path: &str) -> super::Map {
+        pollster::block_on(super::super::load_map(path)).unwrap()
+    }
+
+    fn save(map: &super::Map, name: &str) -> String {
+        std::fs::create_dir_all(SAVED).unwrap();
+        let path = format!("{}/{}", SAVED, name);
+        super::save_map(map, &path).unwrap();
+        path
+    }
+
+    /// `map` with the paths of its tileset images made absolute, to compare maps
+    /// loaded from different directories.
+    fn canonical(mut map: super::Map) -> super::Map {
+        for tileset in &mut map.tilesets {
+            tileset.image_path = std::fs::canonicalize(&tileset.image_path)
+                .unwrap()
+                .to_string_lossy()
+                .into_owned();
+        }
+        map
+    }
+
+    #[test]
+    fn round_trips_mapa() {
+        let path = save(&load(MAP), "mapa.json");
+        let reloaded = load(&path);
+
+        assert_eq!(canonical(load(MAP)), canonical(reloaded));
+        assert_eq!(
+            super::to_json(&load(MAP), &path),
+            super::to_json(&load(&path), &path)
+        );
+    }
+
+    #[test]
+    fn exports_generated_tileset_images() {
+        let mut image = std::io::Cursor::new(vec![]);
+        image::DynamicImage::ImageRgba8(image::RgbaImage::new(32, 16))
+            .write_to(&mut image, image::ImageOutputFormat::Png)
+            .unwrap();
+        let mut map = load(MAP);
+        let first_gid = map
+            .tilesets
+            .iter()
+            .map(|tileset| tileset.first_gid + tileset.tile_count)
+            .max()
+            .unwrap();
+        map.tilesets.push(super::TileSet {
+            name: "walls".to_string(),
+            first_gid,
+            image: image.into_inner(),
+            image_path: String::new(),
+            columns: 2,
+            tile_count: 2,
+            tile_size: (16, 16),
+            margin: 0,
+            spacing: 0,
+            image_size: (32, 16),
+            properties: super::Properties::new(),
+            tiles: Default::default(),
+            wang_sets: Vec::new(),
+        });
+
+        let path = save(&map, "generated.json");
+        let reloaded = load(&path);
+        let tileset = reloaded.tilesets.last().unwrap();
+        assert_eq!(tileset.image_path, format!("{}/generated.walls.png", SAVED));
+        assert_eq!(tileset.image, map.tilesets.last().unwrap().image);
+        assert_eq!(tileset.image_size, (32, 16));
+    }
+}
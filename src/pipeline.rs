@@ -0,0 +1,488 @@
+use wgpu::util::DeviceExt;
+
+/// Shaders pipelines are built with. Their bind groups are the material at 0, the
+/// camera at 1 and, for the ones drawing map layers, the `LayerUniform` at 2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Shader {
+    /// `main.wgsl`, instanced sprites, each a region of the texture.
+    Sprite,
+    /// `tile.wgsl`, instanced tiles of a tileset.
+    Tile,
+    /// `image.wgsl`, a single textured quad.
+    Image,
+    /// `fade.wgsl`, a triangle covering the screen, without vertex buffers.
+    Fade,
+}
+
+impl Shader {
+    fn source(&self) -> &'static str {
+        match self {
+            Shader::Sprite => include_str!("shaders/main.wgsl"),
+            Shader::Tile => include_str!("shaders/tile.wgsl"),
+            Shader::Image => include_str!("shaders/image.wgsl"),
+            Shader::Fade => include_str!("shaders/fade.wgsl"),
+        }
+    }
+
+    /// Layout of the instance buffer, at slot 1, of the instanced shaders.
+    fn instance_layout(&self) -> Option<wgpu::VertexBufferLayout<'static>> {
+        let (array_stride, attributes): (usize, &'static [wgpu::VertexAttribute]) = match self {
+            Shader::Sprite => (
+                std::mem::size_of::<super::batch::SpriteRaw>(),
+                &SPRITE_ATTRIBUTES,
+            ),
+            Shader::Tile => (
+                std::mem::size_of::<super::transform::TransformRaw>(),
+                &TRANSFORM_ATTRIBUTES,
+            ),
+            Shader::Image | Shader::Fade => return None,
+        };
+        Some(wgpu::VertexBufferLayout {
+            array_stride: array_stride as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes,
+        })
+    }
+}
+
+/// How the colors drawn are blended with the target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    /// Colors replace the target, transparent pixels included, only alpha is blended.
+    Replace,
+    /// Colors are blended by their alpha, for translucent layers.
+    Alpha,
+}
+
+impl BlendMode {
+    fn state(&self) -> wgpu::BlendState {
+        match self {
+            BlendMode::Replace => wgpu::BlendState {
+                color: wgpu::BlendComponent::REPLACE,
+                alpha: wgpu::BlendComponent::OVER,
+            },
+            BlendMode::Alpha => wgpu::BlendState::ALPHA_BLENDING,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PipelineKey {
+    pub shader: Shader,
+    pub blend: BlendMode,
+    pub format: wgpu::TextureFormat,
+}
+
+/// Grid of sprites of a texture, in pixels, as Tiled lays tileset images out: `margin`
+/// around the image and `spacing` between sprites, which are numbered row by row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpriteSheet {
+    pub columns: u32,
+    pub rows: u32,
+    pub tile_size: (u32, u32),
+    pub margin: u32,
+    pub spacing: u32,
+}
+
+impl SpriteSheet {
+    /// Sheet of as many `tile_size` sprites as fit in an image of `image_size`.
+    pub fn from_grid(
+        image_size: (u32, u32),
+        tile_size: (u32, u32),
+        margin: u32,
+        spacing: u32,
+    ) -> Self {
+        let fit = |image: u32, tile: u32| {
+            (image.saturating_sub(2 * margin) + spacing) / (tile + spacing).max(1)
+        };
+        Self {
+            columns: fit(image_size.0, tile_size.0),
+            rows: fit(image_size.1, tile_size.1),
+            tile_size,
+            margin,
+            spacing,
+        }
+    }
+
+    /// Left, top, width and height in pixels of the sprite `index`.
+    pub fn rect(&self, index: u32) -> (u32, u32, u32, u32) {
+        let columns = self.columns.max(1);
+        let (column, row) = (index % columns, index / columns);
+        (
+            self.margin + column * (self.tile_size.0 + self.spacing),
+            self.margin + row * (self.tile_size.1 + self.spacing),
+            self.tile_size.0,
+            self.tile_size.1,
+        )
+    }
+
+    fn to_uniform(self) -> SpriteSheetUniform {
+        SpriteSheetUniform {
+            tile_size: [self.tile_size.0, self.tile_size.1],
+            columns: self.columns,
+            rows: self.rows,
+            margin: self.margin,
+            spacing: self.spacing,
+            _padding: [0; 2],
+        }
+    }
+}
+
+/// `SpriteSheet` as the tile shader reads it.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SpriteSheetUniform {
+    tile_size: [u32; 2],
+    columns: u32,
+    rows: u32,
+    margin: u32,
+    spacing: u32,
+    _padding: [u32; 2],
+}
+
+/// A pipeline and the bind groups it draws with, but the camera and the layer ones.
+#[derive(Clone)]
+pub struct Material {
+    pub pipeline: std::rc::Rc<wgpu::RenderPipeline>,
+    pub bind_group: std::rc::Rc<wgpu::BindGroup>,
+}
+
+/// Materials are equal when they're the same pipeline and bind group, and so can
+/// be drawn together.
+impl PartialEq for Material {
+    fn eq(&self, other: &Material) -> bool {
+        std::rc::Rc::ptr_eq(&self.pipeline, &other.pipeline)
+            && std::rc::Rc::ptr_eq(&self.bind_group, &other.bind_group)
+    }
+}
+
+const SPRITE_ATTRIBUTES: [wgpu::VertexAttribute; 5] = wgpu::vertex_attr_array![
+    5 => Float32x4,
+    6 => Float32x4,
+    7 => Float32x4,
+    8 => Float32x4,
+    9 => Float32x4,
+];
+
+const TRANSFORM_ATTRIBUTES: [wgpu::VertexAttribute; 8] = wgpu::vertex_attr_array![
+    5 => Float32x4,
+    6 => Float32x4,
+    7 => Float32x4,
+    8 => Float32x4,
+    9 => Sint32,
+    10 => Sint32,
+    11 => Sint32,
+    12 => Sint32,
+];
+
+const VERTEX_ATTRIBUTES: [wgpu::VertexAttribute; 2] =
+    wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x2];
+
+/// Builds the render pipelines, once per `PipelineKey`, and the bind group layouts
+/// they share.
+pub struct Pipelines {
+    pub camera_bind_group_layout: wgpu::BindGroupLayout,
+    /// Texture and sampler of the sprite and image shaders.
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    /// Texture, sampler and `SpriteSheet` of the tile shader.
+    sprite_sheet_bind_group_layout: wgpu::BindGroupLayout,
+    layer_bind_group_layout: wgpu::BindGroupLayout,
+    /// Color of the fade shader.
+    fade_bind_group_layout: wgpu::BindGroupLayout,
+    cache: std::cell::RefCell<
+        std::collections::HashMap<PipelineKey, std::rc::Rc<wgpu::RenderPipeline>>,
+    >,
+}
+
+impl Pipelines {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let uniform_entry =
+            |binding: u32, visibility: wgpu::ShaderStages| wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            };
+        let texture_entries = [
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            uniform_entry(2, wgpu::ShaderStages::FRAGMENT),
+        ];
+
+        Self {
+            camera_bind_group_layout: device.create_bind_group_layout(
+                &wgpu::BindGroupLayoutDescriptor {
+                    entries: &[uniform_entry(0, wgpu::ShaderStages::VERTEX)],
+                    label: Some("camera_bind_group_layout"),
+                },
+            ),
+            texture_bind_group_layout: device.create_bind_group_layout(
+                &wgpu::BindGroupLayoutDescriptor {
+                    entries: &texture_entries[..2],
+                    label: Some("texture_bind_group_layout"),
+                },
+            ),
+            sprite_sheet_bind_group_layout: device.create_bind_group_layout(
+                &wgpu::BindGroupLayoutDescriptor {
+                    entries: &texture_entries,
+                    label: Some("sprite_sheet_bind_group_layout"),
+                },
+            ),
+            layer_bind_group_layout: device.create_bind_group_layout(
+                &wgpu::BindGroupLayoutDescriptor {
+                    entries: &[uniform_entry(0, wgpu::ShaderStages::VERTEX_FRAGMENT)],
+                    label: Some("layer_bind_group_layout"),
+                },
+            ),
+            fade_bind_group_layout: device.create_bind_group_layout(
+                &wgpu::BindGroupLayoutDescriptor {
+                    entries: &[uniform_entry(0, wgpu::ShaderStages::FRAGMENT)],
+                    label: Some("fade_bind_group_layout"),
+                },
+            ),
+            cache: Default::default(),
+        }
+    }
+
+    /// Pipeline of `key`, built the first time it's asked for.
+    pub fn get(
+        &self,
+        device: &wgpu::Device,
+        key: PipelineKey,
+    ) -> std::rc::Rc<wgpu::RenderPipeline> {
+        self.cache
+            .borrow_mut()
+            .entry(key)
+            .or_insert_with(|| std::rc::Rc::new(self.create_pipeline(device, key)))
+            .clone()
+    }
+
+    fn create_pipeline(&self, device: &wgpu::Device, key: PipelineKey) -> wgpu::RenderPipeline {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(key.shader.source())),
+        });
+
+        let bind_group_layouts: &[&wgpu::BindGroupLayout] = match key.shader {
+            Shader::Sprite => &[
+                &self.texture_bind_group_layout,
+                &self.camera_bind_group_layout,
+            ],
+            Shader::Tile => &[
+                &self.sprite_sheet_bind_group_layout,
+                &self.camera_bind_group_layout,
+                &self.layer_bind_group_layout,
+            ],
+            Shader::Image => &[
+                &self.texture_bind_group_layout,
+                &self.camera_bind_group_layout,
+                &self.layer_bind_group_layout,
+            ],
+            Shader::Fade => &[&self.fade_bind_group_layout],
+        };
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Render Pipeline Layout"),
+                bind_group_layouts,
+                push_constant_ranges: &[],
+            });
+
+        let buffers = match key.shader {
+            Shader::Fade => vec![],
+            _ => std::iter::once(wgpu::VertexBufferLayout {
+                array_stride: std::mem::size_of::<super::vertex::Vertex>() as wgpu::BufferAddress,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &VERTEX_ATTRIBUTES,
+            })
+            .chain(key.shader.instance_layout())
+            .collect(),
+        };
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &buffers,
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: key.format,
+                    blend: Some(key.blend.state()),
+                    write_mask: wgpu::ColorWrites::all(),
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                unclipped_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        })
+    }
+
+    /// Bind group of a texture, drawn with `sampler`, for the sprite and image shaders.
+    pub fn texture_bind_group(
+        &self,
+        device: &wgpu::Device,
+        view: &wgpu::TextureView,
+        sampler: &wgpu::Sampler,
+    ) -> std::rc::Rc<wgpu::BindGroup> {
+        std::rc::Rc::new(device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.texture_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+            label: Some("diffuse_bind_group"),
+        }))
+    }
+
+    /// Bind group of a texture and the layout of its tiles, for the tile shader.
+    pub fn sprite_sheet_bind_group(
+        &self,
+        device: &wgpu::Device,
+        texture: &super::texture::Texture,
+        sheet: SpriteSheet,
+    ) -> std::rc::Rc<wgpu::BindGroup> {
+        let sheet_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Sprite Sheet Buffer"),
+            contents: bytemuck::cast_slice(&[sheet.to_uniform()]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        std::rc::Rc::new(device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.sprite_sheet_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: sheet_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("sprite_sheet_bind_group"),
+        }))
+    }
+
+    /// Bind group of a `LayerUniform` buffer, for the tile and image shaders.
+    pub fn layer_bind_group(
+        &self,
+        device: &wgpu::Device,
+        layer_buffer: &wgpu::Buffer,
+    ) -> std::rc::Rc<wgpu::BindGroup> {
+        std::rc::Rc::new(device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.layer_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: layer_buffer.as_entire_binding(),
+            }],
+            label: Some("layer_bind_group"),
+        }))
+    }
+
+    /// Bind group of the color buffer of the fade shader.
+    pub fn fade_bind_group(&self, device: &wgpu::Device, buffer: &wgpu::Buffer) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.fade_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+            label: Some("fade_bind_group"),
+        })
+    }
+
+    /// Material drawing regions of `texture` with the sprite shader, see `batch::Sprite`.
+    pub fn sprite_material(
+        &self,
+        device: &wgpu::Device,
+        texture: &super::texture::Texture,
+        blend: BlendMode,
+        format: wgpu::TextureFormat,
+    ) -> Material {
+        Material {
+            pipeline: self.get(
+                device,
+                PipelineKey {
+                    shader: Shader::Sprite,
+                    blend,
+                    format,
+                },
+            ),
+            bind_group: self.texture_bind_group(device, &texture.view, &texture.sampler),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SpriteSheet;
+
+    /// 16×8 sprites of 64×64 pixels, 2 pixels of margin and 4 of spacing.
+    const IMAGE_SIZE: (u32, u32) = (2 * 2 + 16 * 64 + 15 * 4, 2 * 2 + 8 * 64 + 7 * 4);
+
+    #[test]
+    fn fits_sprites_between_margin_and_spacing() {
+        let sheet = SpriteSheet::from_grid(IMAGE_SIZE, (64, 64), 2, 4);
+        assert_eq!((sheet.columns, sheet.rows), (16, 8));
+
+        // Leftover pixels short of a sprite don't make another column or row
+        let sheet = SpriteSheet::from_grid((IMAGE_SIZE.0 + 67, IMAGE_SIZE.1 + 1), (64, 64), 2, 4);
+        assert_eq!((sheet.columns, sheet.rows), (16, 8));
+    }
+
+    #[test]
+    fn sprites_skip_margin_and_spacing() {
+        let sheet = SpriteSheet::from_grid(IMAGE_SIZE, (64, 64), 2, 4);
+        assert_eq!(sheet.rect(0), (2, 2, 64, 64));
+        assert_eq!(sheet.rect(1), (70, 2, 64, 64));
+        assert_eq!(sheet.rect(16), (2, 70, 64, 64));
+
+        let (x, y, width, height) = sheet.rect(sheet.columns * sheet.rows - 1);
+        assert_eq!((x, y), (1022, 478));
+        assert_eq!((x + width + 2, y + height + 2), IMAGE_SIZE);
+    }
+}
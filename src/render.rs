@@ -46,6 +46,57 @@ impl Render {
     //     }
     // }
 
+    /// A quad `half_size` from its center to its sides, drawn once per transform of
+    /// `instances` with `material`. `bind_groups` follow the material one, at 1 onwards.
+    pub fn instanced(
+        device: &wgpu::Device,
+        material: &super::pipeline::Material,
+        bind_groups: &[&std::rc::Rc<wgpu::BindGroup>],
+        half_size: nalgebra_glm::Vec2,
+        instances: &[super::transform::TransformRaw],
+    ) -> Self {
+        let (vertex_points, vertex_indices) =
+            super::vertex::get_rect(nalgebra_glm::vec3(half_size.x, half_size.y, 0.0));
+        let vertex_buffer = wgpu::util::DeviceExt::create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::cast_slice(&vertex_points),
+                usage: wgpu::BufferUsages::VERTEX,
+            },
+        );
+        let index_buffer = wgpu::util::DeviceExt::create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::cast_slice(&vertex_indices),
+                usage: wgpu::BufferUsages::INDEX,
+            },
+        );
+        let instance_buffer = wgpu::util::DeviceExt::create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Instance Buffer"),
+                contents: bytemuck::cast_slice(instances),
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            },
+        );
+
+        Self {
+            vertex_buffer,
+            index_buffer,
+            render_pipeline: material.pipeline.clone(),
+            index_count: vertex_indices.len() as _,
+            transform_buffer: Some(std::rc::Rc::new(instance_buffer)),
+            bind_groups: std::iter::once(&material.bind_group)
+                .chain(bind_groups.iter().copied())
+                .enumerate()
+                .map(|(id, bind_group)| (id as u32, bind_group.clone()))
+                .collect(),
+            instances: instances.len() as u32,
+        }
+    }
+
     pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
         render_pass.set_pipeline(&self.render_pipeline);
 
@@ -62,10 +113,4 @@ impl Render {
         render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
         render_pass.draw_indexed(0..self.index_count, 0, 0..self.instances);
     }
-
-    pub fn update_transform(&mut self, data: &[u8], queue: &mut wgpu::Queue) {
-        if let Some(buffer) = &mut self.transform_buffer {
-            queue.write_buffer(&buffer, 0, data);
-        }
-    }
 }
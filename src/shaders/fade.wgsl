@@ -0,0 +1,22 @@
+// Triangle covering the whole screen, filled with the fade color
+
+struct FadeUniform {
+    color: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> fade: FadeUniform;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    // (-1, -1), (3, -1) and (-1, 3), the screen is the corner of the triangle
+    let corner = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(corner * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// Fragment shader
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return fade.color;
+}
@@ -0,0 +1,57 @@
+// Vertex shader
+
+const OPENGL_TO_WGPU_MATRIX: mat4x4<f32> = mat4x4<f32>(
+    vec4<f32>(1.0, 0.0, 0.0, 0.0),
+    vec4<f32>(0.0, 1.0, 0.0, 0.0),
+    vec4<f32>(0.0, 0.0, 0.5, 0.0),
+    vec4<f32>(0.0, 0.0, 0.5, 1.0),
+);
+
+struct CameraUniform {
+    projection: mat4x4<f32>,
+    view: mat4x4<f32>,
+}
+
+@group(1) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct LayerUniform {
+    tint_color: vec4<f32>,
+    shift: vec2<f32>,
+    _padding: vec2<f32>,
+}
+
+@group(2) @binding(0)
+var<uniform> layer: LayerUniform;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+}
+
+@vertex
+fn vs_main(model: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.tex_coords = model.tex_coords;
+    let world_position = vec4<f32>(model.position, 1.0) + vec4<f32>(layer.shift, 0.0, 0.0);
+    out.clip_position = OPENGL_TO_WGPU_MATRIX * camera.projection * camera.view * world_position;
+    return out;
+}
+
+// Fragment shader
+
+@group(0) @binding(0)
+var t_diffuse: texture_2d<f32>;
+@group(0) @binding(1)
+var s_diffuse: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Texture coordinates go past 1 where the image repeats, the sampler wraps them
+    return textureSample(t_diffuse, s_diffuse, in.tex_coords) * layer.tint_color;
+}
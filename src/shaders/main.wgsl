@@ -33,35 +33,31 @@ struct VertexInput {
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
-    @location(1) tex_index: i32,
-    @location(2) tex_flip_x: i32,
 }
 
-struct TransformInput {
+struct SpriteInput {
     @location(5) matrix_0: vec4<f32>,
     @location(6) matrix_1: vec4<f32>,
     @location(7) matrix_2: vec4<f32>,
     @location(8) matrix_3: vec4<f32>,
-    @location(9) index: i32,
-    @location(10) tex_flip_x: i32,
+    // Texture coordinates of the top-left and bottom-right corners of the sprite
+    @location(9) uv: vec4<f32>,
 };
 
 @vertex
 fn vs_main(
     model: VertexInput,
-    transform: TransformInput
+    sprite: SpriteInput
 ) -> VertexOutput {
     let transform_matrix = mat4x4<f32>(
-        transform.matrix_0,
-        transform.matrix_1,
-        transform.matrix_2,
-        transform.matrix_3,
+        sprite.matrix_0,
+        sprite.matrix_1,
+        sprite.matrix_2,
+        sprite.matrix_3,
     );
 
     var out: VertexOutput;
-    out.tex_coords = model.tex_coords;
-    out.tex_index = transform.index;
-    out.tex_flip_x = transform.tex_flip_x;
+    out.tex_coords = mix(sprite.uv.xy, sprite.uv.zw, model.tex_coords);
     out.clip_position = OPENGL_TO_WGPU_MATRIX * camera.projection * camera.view * transform_matrix * vec4<f32>(model.position, 1.0);
     return out;
 }
@@ -75,19 +71,7 @@ var s_diffuse: sampler;
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    let elementIndex = in.tex_index; // El índice del elemento que deseas dibujar
-    let elementsPerRow = 10; // Número de elementos por fila
-    let elementsPerColumn = 25; // Número de elementos por columna
-    let elementColumn = elementIndex % elementsPerRow;
-    let elementRow = elementIndex / elementsPerRow;
-
-    let flip_x = select(in.tex_coords.x, 1.0 - in.tex_coords.x, in.tex_flip_x == 0);
-    let tex_coords = vec2<f32>(
-        (flip_x + f32(elementColumn)) / f32(elementsPerRow),
-        (in.tex_coords.y + f32(elementRow)) / f32(elementsPerColumn)
-    );
-
-    var color = textureSample(t_diffuse, s_diffuse, tex_coords);
+    var color = textureSample(t_diffuse, s_diffuse, in.tex_coords);
     if (color.a == 0.0) {
         discard;
     }
@@ -15,6 +15,15 @@ struct CameraUniform {
 @group(1) @binding(0)
 var<uniform> camera: CameraUniform;
 
+struct LayerUniform {
+    tint_color: vec4<f32>,
+    shift: vec2<f32>,
+    _padding: vec2<f32>,
+}
+
+@group(2) @binding(0)
+var<uniform> layer: LayerUniform;
+
 
 // struct UniformsTexture {
 //     texture_index: u32,
@@ -35,6 +44,8 @@ struct VertexOutput {
     @location(0) tex_coords: vec2<f32>,
     @location(1) tex_index: i32,
     @location(2) tex_flip_x: i32,
+    @location(3) tex_flip_y: i32,
+    @location(4) tex_flip_diagonal: i32,
 }
 
 struct TransformInput {
@@ -44,6 +55,8 @@ struct TransformInput {
     @location(8) matrix_3: vec4<f32>,
     @location(9) index: i32,
     @location(10) tex_flip_x: i32,
+    @location(11) tex_flip_y: i32,
+    @location(12) tex_flip_diagonal: i32,
 };
 
 @vertex
@@ -62,7 +75,10 @@ fn vs_main(
     out.tex_coords = model.tex_coords;
     out.tex_index = transform.index;
     out.tex_flip_x = transform.tex_flip_x;
-    out.clip_position = OPENGL_TO_WGPU_MATRIX * camera.projection * camera.view * transform_matrix * vec4<f32>(model.position, 1.0);
+    out.tex_flip_y = transform.tex_flip_y;
+    out.tex_flip_diagonal = transform.tex_flip_diagonal;
+    let world_position = transform_matrix * vec4<f32>(model.position, 1.0) + vec4<f32>(layer.shift, 0.0, 0.0);
+    out.clip_position = OPENGL_TO_WGPU_MATRIX * camera.projection * camera.view * world_position;
     return out;
 }
 
@@ -73,24 +89,39 @@ var t_diffuse: texture_2d<f32>;
 @group(0) @binding(1)
 var s_diffuse: sampler;
 
+struct SpriteSheetUniform {
+    tile_size: vec2<u32>,
+    columns: u32,
+    rows: u32,
+    margin: u32,
+    spacing: u32,
+    _padding: vec2<u32>,
+}
+
+@group(0) @binding(2)
+var<uniform> sheet: SpriteSheetUniform;
+
+// Texture coordinates of `uv` inside the sprite `index` of the sheet
+fn sprite_coords(index: i32, uv: vec2<f32>) -> vec2<f32> {
+    let cell = vec2<u32>(u32(index) % sheet.columns, u32(index) / sheet.columns);
+    let origin = vec2<f32>(sheet.margin + cell * (sheet.tile_size + sheet.spacing));
+    return (origin + uv * vec2<f32>(sheet.tile_size)) / vec2<f32>(textureDimensions(t_diffuse));
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    let elementIndex = in.tex_index; // El índice del elemento que deseas dibujar
-    let elementsPerRow = 64; // Número de elementos por fila
-    let elementsPerColumn = 64; // Número de elementos por columna
-    let elementColumn = elementIndex % elementsPerRow;
-    let elementRow = elementIndex / elementsPerRow;
-
-    let flip_x = select(in.tex_coords.x, 1.0 - in.tex_coords.x, in.tex_flip_x == 0);
-    let tex_coords = vec2<f32>(
-        (flip_x + f32(elementColumn)) / f32(elementsPerRow),
-        (in.tex_coords.y + f32(elementRow)) / f32(elementsPerColumn)
-    );
-
-    var color = textureSample(t_diffuse, s_diffuse, tex_coords);
+    // Tiled flips diagonally first, then horizontally and vertically,
+    // so sampling undoes them in the reverse order
+    var uv = in.tex_coords;
+    uv.y = select(uv.y, 1.0 - uv.y, in.tex_flip_y == 1);
+    uv.x = select(uv.x, 1.0 - uv.x, in.tex_flip_x == 0);
+    uv = select(uv, uv.yx, in.tex_flip_diagonal == 1);
+
+    var color = textureSample(t_diffuse, s_diffuse, sprite_coords(in.tex_index, uv));
     if (color.a == 0.0) {
         discard;
     }
+    color *= layer.tint_color;
 
     return color;
 }
\ No newline at end of file
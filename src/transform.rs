@@ -7,6 +7,11 @@ pub struct Transform {
     pub matrix: nalgebra_glm::Mat4,
     pub index: i32,
     pub flip_x: i32,
+    /// 1 when mirrored vertically. Unlike `flip_x`, 0 keeps the sprite untouched.
+    pub flip_y: i32,
+    /// 1 when flipped along the main diagonal, top-left to bottom-right (x and y
+    /// swapped), applied before the other flips.
+    pub flip_diagonal: i32,
     parent: Option<std::rc::Weak<std::cell::RefCell<Transform>>>,
     children: Vec<std::rc::Rc<std::cell::RefCell<Transform>>>,
 }
@@ -23,6 +28,8 @@ impl Transform {
             matrix: nalgebra_glm::Mat4::identity(),
             index: 0,
             flip_x: 0,
+            flip_y: 0,
+            flip_diagonal: 0,
             parent: None,
             children: vec![],
             label: None,
@@ -35,12 +42,12 @@ impl Transform {
         parent.as_ref().borrow_mut().children.push(child.clone());
         child.as_ref().borrow_mut().parent = Some(std::rc::Rc::downgrade(&parent.clone()));
 
-        let child_position = child.as_ref().borrow().position.clone();
-        let parent_position = parent.as_ref().borrow().global_position().clone();
-        let child_scale = child.as_ref().borrow().scale.clone();
-        let parent_scale = parent.as_ref().borrow().scale.clone();
-        let child_rotation = child.as_ref().borrow().rotation.clone();
-        let parent_rotation = parent.as_ref().borrow().global_rotation().clone();
+        let child_position = child.as_ref().borrow().position;
+        let parent_position = parent.as_ref().borrow().global_position();
+        let child_scale = child.as_ref().borrow().scale;
+        let parent_scale = parent.as_ref().borrow().scale;
+        let child_rotation = child.as_ref().borrow().rotation;
+        let parent_rotation = parent.as_ref().borrow().global_rotation();
 
         let new_position = child_position - parent_position;
         let new_rotation = child_rotation - parent_rotation;
@@ -84,6 +91,8 @@ impl Transform {
             transform: self.matrix.into(),
             index: self.index,
             flip_x: self.flip_x,
+            flip_y: self.flip_y,
+            flip_diagonal: self.flip_diagonal,
         }
     }
 
@@ -218,4 +227,6 @@ pub struct TransformRaw {
     pub transform: [[f32; 4]; 4],
     pub index: i32,
     pub flip_x: i32,
+    pub flip_y: i32,
+    pub flip_diagonal: i32,
 }
@@ -5,6 +5,15 @@ pub struct Vertex {
     tex_coords: [f32; 2],
 }
 
+impl Vertex {
+    pub fn new(position: [f32; 3], tex_coords: [f32; 2]) -> Self {
+        Self {
+            position,
+            tex_coords,
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct UniformsTexture {